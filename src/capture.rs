@@ -0,0 +1,488 @@
+use crate::tilings::{Displays, FrameMode, Rule};
+use crate::WintError;
+use std::path::Path;
+
+/// A tag in the XML text, from `<` to `>`
+#[derive(Debug, Clone, Copy)]
+struct Tag<'a> {
+    start: usize,
+    end: usize,
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+}
+
+/// The tags of `xml`, skipping comments, processing instructions and declarations
+fn tags(xml: &str) -> Vec<Tag<'_>> {
+    let mut tags = vec![];
+    let mut i = 0;
+    while let Some(offset) = xml[i..].find('<') {
+        let start = i + offset;
+        let rest = &xml[start..];
+        let skip_to = if rest.starts_with("<!--") {
+            Some("-->")
+        } else if rest.starts_with("<?") {
+            Some("?>")
+        } else if rest.starts_with("<!") {
+            Some(">")
+        } else {
+            None
+        };
+        if let Some(terminator) = skip_to {
+            i = match rest.find(terminator) {
+                Some(e) => start + e + terminator.len(),
+                None => xml.len(),
+            };
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(e) => start + e + 1,
+            None => break,
+        };
+        let inner = &xml[start + 1..end - 1];
+        let closing = inner.starts_with('/');
+        let self_closing = inner.ends_with('/');
+        let name = inner
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("");
+        tags.push(Tag {
+            start,
+            end,
+            name,
+            closing,
+            self_closing,
+        });
+        i = end;
+    }
+    tags
+}
+
+/// The position of the value of the attribute `name` in the `tag` text
+fn attribute_span(tag: &str, name: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(offset) = tag[from..].find(name) {
+        let at = from + offset;
+        from = at + name.len();
+        let preceded_by_space = tag[..at].ends_with(|c: char| c.is_whitespace());
+        let after = tag[from..].trim_start();
+        if !preceded_by_space || !after.starts_with('=') {
+            continue;
+        }
+        let value = after[1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let value_start = tag.len() - value.len() + 1;
+        let value_end = value_start + tag[value_start..].find(quote)?;
+        return Some((value_start, value_end));
+    }
+    None
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    attribute_span(tag, name).map(|(s, e)| &tag[s..e])
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The whitespace at the beginning of the line containing `pos`
+fn indentation(xml: &str, pos: usize) -> &str {
+    let line_start = xml[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = &xml[line_start..pos];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// The `<window>` tags in the `<display>` which is `tags[display_index]`
+fn section_windows<'t, 'a>(
+    xml: &str,
+    tags: &'t [Tag<'a>],
+    display_index: usize,
+) -> Result<Vec<&'t Tag<'a>>, String> {
+    let display = &tags[display_index];
+    if display.self_closing {
+        return Ok(vec![]);
+    }
+    let display_end = tags[display_index + 1..]
+        .iter()
+        .find(|t| t.name == "display" && t.closing)
+        .ok_or(format!(
+            "{} is not closed",
+            &xml[display.start..display.end]
+        ))?;
+    Ok(tags[display_index + 1..]
+        .iter()
+        .take_while(|t| t.start < display_end.start)
+        .filter(|t| t.name == "window" && !t.closing)
+        .collect())
+}
+
+/// The `<window>` called `nick` among `windows`
+fn find_nick<'t, 'a>(xml: &str, windows: &[&'t Tag<'a>], nick: &str) -> Option<&'t Tag<'a>> {
+    windows
+        .iter()
+        .find(|t| {
+            attribute(&xml[t.start..t.end], "nick")
+                .map(unescape)
+                .as_deref()
+                == Some(nick)
+        })
+        .copied()
+}
+
+/// The `tag` text with the attribute `name` set to `value`, added at the end if it is not there
+fn set_attribute(tag: &str, name: &str, value: &str) -> String {
+    match attribute_span(tag, name) {
+        Some((s, e)) => format!("{}{}{}", &tag[..s], value, &tag[e..]),
+        None => {
+            let cut = tag.len() - if tag.ends_with("/>") { 2 } else { 1 };
+            format!(
+                "{} {}=\"{}\"{}",
+                tag[..cut].trim_end(),
+                name,
+                value,
+                &tag[cut..]
+            )
+        }
+    }
+}
+
+/// `xml` with the geometry of the `window` tag replaced, for the frame mode of the window; the geometry
+/// is marked as exact, so that the gap and the outer margin do not shrink it
+fn replace_geometry(xml: &str, window: &Tag, geometry: &dyn Fn(FrameMode) -> String) -> String {
+    let text = &xml[window.start..window.end];
+    let frame = match attribute(text, "frame") {
+        Some("client") => FrameMode::Client,
+        _ => FrameMode::Outer,
+    };
+    let new_text = set_attribute(
+        &set_attribute(text, "geometry", &geometry(frame)),
+        "exact",
+        "true",
+    );
+    format!("{}{}{}", &xml[..window.start], new_text, &xml[window.end..])
+}
+
+/// Sets the geometry of the `<window>` called `nick` in the `<display>` for `resolution`, adding the window
+/// (and the display) if needed. If the `<display>` for `output` has the nick, that one is set instead, as it
+/// comes first when the nick is looked up. The geometry is given by `geometry` for the frame mode of the window,
+/// so that a window with `frame="client"` keeps it. Everything else in `xml` stays as it was. Gives the new text,
+/// and which section was changed
+pub fn set_window_geometry(
+    xml: &str,
+    output: &str,
+    resolution: &str,
+    nick: &str,
+    geometry: &dyn Fn(FrameMode) -> String,
+) -> Result<(String, Rule), String> {
+    let tags = tags(xml);
+    let output_section = tags.iter().position(|t| {
+        t.name == "display"
+            && !t.closing
+            && attribute(&xml[t.start..t.end], "output")
+                .map(unescape)
+                .as_deref()
+                == Some(output)
+    });
+    if let Some(display_index) = output_section {
+        let windows = section_windows(xml, &tags, display_index)?;
+        if let Some(window) = find_nick(xml, &windows, nick) {
+            return Ok((
+                replace_geometry(xml, window, geometry),
+                Rule::Output(output.to_string()),
+            ));
+        }
+    }
+    let updated = set_in_resolution_section(xml, &tags, resolution, nick, geometry)?;
+    Ok((updated, Rule::Exact))
+}
+
+/// Sets the geometry of `nick` in the `<display>` for `resolution`, as `set_window_geometry` does
+fn set_in_resolution_section(
+    xml: &str,
+    tags: &[Tag],
+    resolution: &str,
+    nick: &str,
+    geometry: &dyn Fn(FrameMode) -> String,
+) -> Result<String, String> {
+    let tag_text = |t: &Tag| &xml[t.start..t.end];
+    // the <display> for the resolution (not one for a particular output)
+    let display = tags.iter().enumerate().find(|(_, t)| {
+        t.name == "display"
+            && !t.closing
+            && attribute(tag_text(t), "resolution") == Some(resolution)
+            && attribute(tag_text(t), "output").is_none()
+    });
+    let (display_index, display) = match display {
+        Some((i, t)) => (i, *t),
+        None => {
+            let end = tags
+                .iter()
+                .rev()
+                .find(|t| t.name == "displays" && t.closing)
+                .ok_or("tilings.xml has no </displays>")?;
+            let indent = tags
+                .iter()
+                .find(|t| t.name == "display" && !t.closing)
+                .map(|t| indentation(xml, t.start))
+                .unwrap_or("  ");
+            let section = format!(
+                "{}<display resolution=\"{}\">\n{}  <window nick=\"{}\" geometry=\"{}\" exact=\"true\"/>\n{}</display>\n",
+                indent,
+                escape(resolution),
+                indent,
+                escape(nick),
+                geometry(FrameMode::Outer),
+                indent
+            );
+            // on its own line before </displays>
+            let line_start = xml[..end.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            if xml[line_start..end.start].trim().is_empty() {
+                return Ok(format!(
+                    "{}{}{}",
+                    &xml[..line_start],
+                    section,
+                    &xml[line_start..]
+                ));
+            }
+            return Ok(format!(
+                "{}\n{}{}",
+                &xml[..end.start],
+                section,
+                &xml[end.start..]
+            ));
+        }
+    };
+    let display_indent = indentation(xml, display.start);
+    let new_window = |indent: &str| {
+        format!(
+            "{}<window nick=\"{}\" geometry=\"{}\" exact=\"true\"/>",
+            indent,
+            escape(nick),
+            geometry(FrameMode::Outer)
+        )
+    };
+    if display.self_closing {
+        let open = tag_text(&display)
+            .trim_end_matches('>')
+            .trim_end_matches('/');
+        let section = format!(
+            "{}>\n{}\n{}</display>",
+            open.trim_end(),
+            new_window(&format!("{}  ", display_indent)),
+            display_indent
+        );
+        return Ok(format!(
+            "{}{}{}",
+            &xml[..display.start],
+            section,
+            &xml[display.end..]
+        ));
+    }
+    let windows = section_windows(xml, tags, display_index)?;
+    if let Some(window) = find_nick(xml, &windows, nick) {
+        return Ok(replace_geometry(xml, window, geometry));
+    }
+    let indent = match windows.last() {
+        Some(w) => indentation(xml, w.start).to_string(),
+        None => format!("{}  ", display_indent),
+    };
+    // after the last window, or else right after the <display> tag
+    let after = windows.last().map(|w| w.end).unwrap_or(display.end);
+    let after = match windows.last() {
+        Some(w) if !w.self_closing => tags
+            .iter()
+            .find(|t| t.start >= w.end && t.name == "window" && t.closing)
+            .map(|t| t.end)
+            .unwrap_or(after),
+        _ => after,
+    };
+    Ok(format!(
+        "{}\n{}{}",
+        &xml[..after],
+        new_window(&indent),
+        &xml[after..]
+    ))
+}
+
+/// Writes the geometry of `nick` on the monitor connected to `output`, with the resolution `resolution`, into
+/// the `tilings.xml` at `xml_path`, as `set_window_geometry` does; the file is left alone if the result could not
+/// be read back. Gives the section which was changed
+pub fn record_geometry(
+    xml_path: &Path,
+    output: &str,
+    resolution: &str,
+    nick: &str,
+    geometry: &dyn Fn(FrameMode) -> String,
+) -> Result<Rule, WintError> {
+    if nick.is_empty()
+        || nick.starts_with('@')
+        || nick.contains(|c: char| c.is_whitespace() || c == '>' || c == '=')
+    {
+        return Err(WintError::BadCommand(format!(
+            "{:?} can not be a nick: it should not be empty, start with \"@\", nor contain spaces, \">\" or \"=\"",
+            nick
+        )));
+    }
+    let xml = std::fs::read_to_string(xml_path)?;
+    let (updated, rule) = set_window_geometry(&xml, output, resolution, nick, geometry)
+        .map_err(WintError::CannotCapture)?;
+    serde_xml_rs::from_str::<Displays>(&updated).map_err(|e| {
+        WintError::CannotCapture(format!("the new tilings.xml would not parse: {}", e))
+    })?;
+    std::fs::write(xml_path, updated)?;
+    Ok(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<?xml version="1.0"?>
+<displays gap="0">
+  <!-- <window nick="tex" geometry="9,9,9,9"/> in a comment -->
+  <display resolution="1920x1080">
+    <window nick="tex" geometry="0,0,1150,1060"/>
+    <window nick="term" geometry="0,0,1/2,1" frame="client"/>
+  </display>
+  <display resolution="1600x900">
+    <window nick="tex" geometry="0,0,930,883"/>
+  </display>
+</displays>
+"#;
+
+    fn geometry(frame: FrameMode) -> String {
+        match frame {
+            FrameMode::Outer => String::from("10,20,300,400"),
+            FrameMode::Client => String::from("12,44,296,374"),
+        }
+    }
+
+    fn set(xml: &str, output: &str, resolution: &str, nick: &str) -> (String, Rule) {
+        set_window_geometry(xml, output, resolution, nick, &geometry).unwrap()
+    }
+
+    #[test]
+    fn updates_an_existing_nick() {
+        let (updated, rule) = set(XML, "DP-1", "1920x1080", "tex");
+        assert_eq!(rule, Rule::Exact);
+        assert_eq!(
+            updated,
+            XML.replace(
+                r#"<window nick="tex" geometry="0,0,1150,1060"/>"#,
+                r#"<window nick="tex" geometry="10,20,300,400" exact="true"/>"#
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_the_frame_mode() {
+        let (updated, _) = set(XML, "DP-1", "1920x1080", "term");
+        assert_eq!(
+            updated,
+            XML.replace(
+                r#"geometry="0,0,1/2,1" frame="client""#,
+                r#"geometry="12,44,296,374" frame="client" exact="true""#
+            )
+        );
+    }
+
+    #[test]
+    fn inserts_a_new_nick_after_the_last_window() {
+        let (updated, _) = set(XML, "DP-1", "1600x900", "pdf");
+        assert_eq!(
+            updated,
+            XML.replace(
+                "    <window nick=\"tex\" geometry=\"0,0,930,883\"/>\n",
+                "    <window nick=\"tex\" geometry=\"0,0,930,883\"/>\n    <window nick=\"pdf\" geometry=\"10,20,300,400\" exact=\"true\"/>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn creates_a_missing_section() {
+        let (updated, rule) = set(XML, "DP-1", "2560x1440", "tex");
+        assert_eq!(rule, Rule::Exact);
+        assert_eq!(
+            updated,
+            XML.replace(
+                "</displays>",
+                "  <display resolution=\"2560x1440\">\n    <window nick=\"tex\" geometry=\"10,20,300,400\" exact=\"true\"/>\n  </display>\n</displays>"
+            )
+        );
+    }
+
+    #[test]
+    fn fills_a_self_closing_section() {
+        let xml = "<displays>\n  <display resolution=\"1920x1080\"/>\n</displays>\n";
+        let (updated, _) = set(xml, "DP-1", "1920x1080", "tex");
+        assert_eq!(
+            updated,
+            "<displays>\n  <display resolution=\"1920x1080\">\n    <window nick=\"tex\" geometry=\"10,20,300,400\" exact=\"true\"/>\n  </display>\n</displays>\n"
+        );
+    }
+
+    #[test]
+    fn updates_the_output_section_which_has_the_nick() {
+        let xml = XML.replace(
+            "  <display resolution=\"1600x900\">",
+            "  <display output=\"HDMI-1\">\n    <window nick=\"tex\" geometry=\"0,0,1/2,1\"/>\n  </display>\n  <display resolution=\"1600x900\">",
+        );
+        let (updated, rule) = set(&xml, "HDMI-1", "1920x1080", "tex");
+        assert_eq!(rule, Rule::Output(String::from("HDMI-1")));
+        assert_eq!(
+            updated,
+            xml.replace(
+                r#"<window nick="tex" geometry="0,0,1/2,1"/>"#,
+                r#"<window nick="tex" geometry="10,20,300,400" exact="true"/>"#
+            )
+        );
+        // a nick which the output section does not have goes into the section for the resolution
+        let (_, rule) = set(&xml, "HDMI-1", "1920x1080", "term");
+        assert_eq!(rule, Rule::Exact);
+    }
+
+    #[test]
+    fn the_result_parses() {
+        for resolution in ["1920x1080", "1600x900", "2560x1440"] {
+            let (updated, _) = set(XML, "DP-1", resolution, "new");
+            let displays: Displays = serde_xml_rs::from_str(&updated).unwrap();
+            let (_, window, _) = displays.lookup("new", "DP-1", resolution).unwrap().unwrap();
+            assert!(window.exact);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_nicks() {
+        for nick in ["", "@tl", "a b", "a>2", "a=b"] {
+            let result = record_geometry(
+                Path::new("/nonexistent/tilings.xml"),
+                "DP-1",
+                "1920x1080",
+                nick,
+                &geometry,
+            );
+            assert!(
+                matches!(result, Err(WintError::BadCommand(_))),
+                "{:?}",
+                nick
+            );
+        }
+    }
+}
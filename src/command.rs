@@ -0,0 +1,374 @@
+use crate::generated::Generated;
+use crate::WintError;
+
+/// One word of a `wint` command: `atex` tiles the window with the charhint `a` as `tex` (or `a@1:0` into
+/// a cell of the grid), and `atex>2` (or `a>2`, without a nick) puts it on the monitor `2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub hint: char,
+    /// the position of the window in the list, i.e. 0 for `a`
+    pub index: usize,
+    pub nick: String,
+    pub target: Option<String>,
+}
+
+/// The position of the window with the charhint `hint` in a list of `windows` windows
+fn hint_index(hint: char, windows: usize) -> Result<usize, WintError> {
+    if !hint.is_ascii_lowercase() || (hint as u8 - b'a') as usize >= windows {
+        return Err(WintError::UnknownHint(hint));
+    }
+    Ok((hint as u8 - b'a') as usize)
+}
+
+/// A command like `:tall cab`: the windows `c`, `a` and `b` tiled with a generated layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedCommand<'a> {
+    pub name: &'a str,
+    pub layout: Generated,
+    /// the positions of the windows in the list, in the order of the layout
+    pub indices: Vec<usize>,
+}
+
+fn parse_step(word: &str, windows: usize) -> Result<Step, WintError> {
+    let mut chars = word.chars();
+    let hint = chars
+        .next()
+        .ok_or(WintError::BadCommand(String::from("empty word")))?;
+    let index = hint_index(hint, windows)?;
+    let rest = chars.as_str();
+    let (nick, target) = match rest.split_once('>') {
+        Some((_, "")) => {
+            return Err(WintError::BadCommand(format!(
+                "no monitor after \">\" in {:?}",
+                word
+            )))
+        }
+        Some((nick, target)) => (nick, Some(target.to_string())),
+        None => (rest, None),
+    };
+    if nick.is_empty() && target.is_none() {
+        return Err(WintError::BadCommand(format!(
+            "no nick for the window {}",
+            hint
+        )));
+    }
+    Ok(Step {
+        hint,
+        index,
+        nick: nick.to_string(),
+        target,
+    })
+}
+
+/// Parses a command like `atex cpdf>2`, for a list of `windows` windows (hinted `a`, `b`, ...)
+pub fn parse_command(command: &str, windows: usize) -> Result<Vec<Step>, WintError> {
+    let mut steps: Vec<Step> = vec![];
+    for word in command.split_whitespace() {
+        let step = parse_step(word, windows)?;
+        if steps.iter().any(|s| s.index == step.index) {
+            return Err(WintError::DuplicateWindow(step.hint));
+        }
+        steps.push(step);
+    }
+    if steps.is_empty() {
+        return Err(WintError::BadCommand(String::from("empty command")));
+    }
+    Ok(steps)
+}
+
+/// Parses a capture command like `a=tex`, which saves the geometry of the window `a` as the nick `tex`;
+/// `None` if `command` is not a capture command
+pub fn parse_capture(command: &str, windows: usize) -> Option<Result<Step, WintError>> {
+    let word = command.trim();
+    let (hint, nick) = word.split_once('=')?;
+    if word.contains(char::is_whitespace) {
+        return Some(Err(WintError::BadCommand(String::from(
+            "only one window can be captured at a time",
+        ))));
+    }
+    let mut chars = hint.chars();
+    let step = match (chars.next(), chars.next()) {
+        (Some(h), None) => hint_index(h, windows).map(|index| Step {
+            hint: h,
+            index,
+            nick: nick.to_string(),
+            target: None,
+        }),
+        _ => Err(WintError::BadCommand(format!(
+            "{:?} should be a charhint, \"=\" and a nick",
+            word
+        ))),
+    };
+    Some(step)
+}
+
+/// Parses a command like `:tall` or `:tall cab`, which tiles the windows with the given charhints
+/// (or else all the `windows`, in order) with a generated layout; `None` if `command` is not a generated layout
+pub fn parse_generated(
+    command: &str,
+    windows: usize,
+) -> Option<Result<GeneratedCommand<'_>, WintError>> {
+    let rest = command.trim().strip_prefix(':')?;
+    let (name, hints) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let layout = match Generated::parse(name) {
+        Ok(g) => g,
+        Err(e) => return Some(Err(e)),
+    };
+    let indices = match parse_hints(hints.chars(), windows) {
+        Ok(i) => i,
+        Err(e) => return Some(Err(e)),
+    };
+    Some(Ok(GeneratedCommand {
+        name,
+        layout,
+        indices,
+    }))
+}
+
+/// The positions of the windows with the `hints` (ignoring spaces), or of all the `windows` if there are no hints
+fn parse_hints(hints: impl Iterator<Item = char>, windows: usize) -> Result<Vec<usize>, WintError> {
+    let mut indices = vec![];
+    for hint in hints.filter(|c| !c.is_whitespace()) {
+        let index = hint_index(hint, windows)?;
+        if indices.contains(&index) {
+            return Err(WintError::DuplicateWindow(hint));
+        }
+        indices.push(index);
+    }
+    if indices.is_empty() {
+        indices = (0..windows).collect();
+    }
+    Ok(indices)
+}
+
+/// Moves windows into the places of other windows, rather than into tilings
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rearrangement {
+    /// each window takes the geometry of the next one, and the last one that of the first one
+    Rotate(Vec<usize>),
+    /// each window is mirrored left to right in the work area of its monitor
+    Mirror(Vec<usize>),
+}
+
+/// Parses `a<>c` (swap two windows), `a<>b<>c` (rotate them), or `:rotate` and `:mirror`, each followed by
+/// the charhints of some windows (otherwise all the `windows`, in order); `None` if `command` is none of these
+pub fn parse_rearrangement(
+    command: &str,
+    windows: usize,
+) -> Option<Result<Rearrangement, WintError>> {
+    let command = command.trim();
+    if command.contains("<>") {
+        let hints: Vec<&str> = command.split("<>").map(str::trim).collect();
+        if hints.iter().any(|h| h.chars().count() != 1) {
+            return Some(Err(WintError::BadCommand(format!(
+                "{:?} should be charhints separated by \"<>\", like a<>c",
+                command
+            ))));
+        }
+        return Some(
+            parse_hints(hints.iter().flat_map(|h| h.chars()), windows).map(Rearrangement::Rotate),
+        );
+    }
+    let rest = command.strip_prefix(':')?;
+    let (name, hints) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let rearrangement: fn(Vec<usize>) -> Rearrangement = match name {
+        "rotate" => Rearrangement::Rotate,
+        "mirror" => Rearrangement::Mirror,
+        _ => return None,
+    };
+    Some(parse_hints(hints.chars(), windows).map(rearrangement))
+}
+
+/// Parses `:move a`, which moves and resizes the window `a` with the arrow keys; gives the position of the window
+/// in the list, or `None` if `command` is not `:move`
+pub fn parse_move(command: &str, windows: usize) -> Option<Result<usize, WintError>> {
+    let rest = command.trim().strip_prefix(':')?;
+    let (name, hints) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if name != "move" {
+        return None;
+    }
+    let mut chars = hints.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(h), None) => Some(hint_index(h, windows)),
+        _ => Some(Err(WintError::BadCommand(String::from(
+            ":move takes the charhint of one window, like :move a",
+        )))),
+    }
+}
+
+/// Completes the last word of `text`, a charhint followed by the beginning of a nick, with each of the `nicks`
+/// which fit; gives the whole completed texts
+pub fn complete(text: &str, nicks: &[String]) -> Vec<String> {
+    let start = text.rfind(' ').map(|i| i + 1).unwrap_or(0);
+    let mut chars = text[start..].chars();
+    let hint = match chars.next() {
+        Some(h) if h.is_ascii_lowercase() => h,
+        _ => return vec![],
+    };
+    let prefix = chars.as_str();
+    if prefix.contains('>') {
+        return vec![];
+    }
+    nicks
+        .iter()
+        .filter(|nick| nick.starts_with(prefix))
+        .map(|nick| format!("{}{}{}", &text[..start], hint, nick))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(hint: char, index: usize, nick: &str, target: Option<&str>) -> Step {
+        Step {
+            hint,
+            index,
+            nick: nick.to_string(),
+            target: target.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parses_steps() {
+        assert_eq!(
+            parse_command("atex  cpdf>2 b>HDMI-1 d@2x2/tl", 4).unwrap(),
+            vec![
+                step('a', 0, "tex", None),
+                step('c', 2, "pdf", Some("2")),
+                step('b', 1, "", Some("HDMI-1")),
+                step('d', 3, "@2x2/tl", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(matches!(
+            parse_command("", 3),
+            Err(WintError::BadCommand(_))
+        ));
+        assert!(matches!(
+            parse_command("a", 3),
+            Err(WintError::BadCommand(_))
+        ));
+        assert!(matches!(
+            parse_command("atex>", 3),
+            Err(WintError::BadCommand(_))
+        ));
+        assert!(matches!(
+            parse_command("dtex", 3),
+            Err(WintError::UnknownHint('d'))
+        ));
+        assert!(matches!(
+            parse_command("Atex", 3),
+            Err(WintError::UnknownHint('A'))
+        ));
+        assert!(matches!(
+            parse_command("atex apdf", 3),
+            Err(WintError::DuplicateWindow('a'))
+        ));
+    }
+
+    #[test]
+    fn parses_captures() {
+        assert_eq!(
+            parse_capture(" b=tex ", 2).unwrap().unwrap(),
+            step('b', 1, "tex", None)
+        );
+        assert!(parse_capture("atex", 2).is_none());
+        assert!(matches!(
+            parse_capture("a=tex b=pdf", 2),
+            Some(Err(WintError::BadCommand(_)))
+        ));
+        assert!(matches!(
+            parse_capture("ab=tex", 2),
+            Some(Err(WintError::BadCommand(_)))
+        ));
+        assert!(matches!(
+            parse_capture("c=tex", 2),
+            Some(Err(WintError::UnknownHint('c')))
+        ));
+    }
+
+    #[test]
+    fn parses_generated_layouts() {
+        let generated = parse_generated(":tall cab", 3).unwrap().unwrap();
+        assert_eq!(generated.name, "tall");
+        assert_eq!(generated.layout, Generated::Tall);
+        assert_eq!(generated.indices, vec![2, 0, 1]);
+        let generated = parse_generated(":cols2", 3).unwrap().unwrap();
+        assert_eq!(generated.layout, Generated::Columns(Some(2)));
+        assert_eq!(generated.indices, vec![0, 1, 2]);
+        assert!(parse_generated("atex", 3).is_none());
+        assert!(matches!(
+            parse_generated(":spiral", 3),
+            Some(Err(WintError::UnknownGenerated(_)))
+        ));
+        assert!(matches!(
+            parse_generated(":grid aa", 3),
+            Some(Err(WintError::DuplicateWindow('a')))
+        ));
+    }
+
+    #[test]
+    fn parses_rearrangements() {
+        assert_eq!(
+            parse_rearrangement("a<>c", 3).unwrap().unwrap(),
+            Rearrangement::Rotate(vec![0, 2])
+        );
+        assert_eq!(
+            parse_rearrangement("a <> b <> c", 3).unwrap().unwrap(),
+            Rearrangement::Rotate(vec![0, 1, 2])
+        );
+        assert_eq!(
+            parse_rearrangement(":rotate", 2).unwrap().unwrap(),
+            Rearrangement::Rotate(vec![0, 1])
+        );
+        assert_eq!(
+            parse_rearrangement(":mirror ba", 2).unwrap().unwrap(),
+            Rearrangement::Mirror(vec![1, 0])
+        );
+        assert!(parse_rearrangement(":tall", 2).is_none());
+        assert!(matches!(
+            parse_rearrangement("ab<>c", 3),
+            Some(Err(WintError::BadCommand(_)))
+        ));
+        assert!(matches!(
+            parse_rearrangement("a<>a", 3),
+            Some(Err(WintError::DuplicateWindow('a')))
+        ));
+    }
+
+    #[test]
+    fn parses_move() {
+        assert_eq!(parse_move(":move b", 2).unwrap().unwrap(), 1);
+        assert!(parse_move(":mirror", 2).is_none());
+        assert!(parse_move("amove", 2).is_none());
+        assert!(matches!(
+            parse_move(":move", 2),
+            Some(Err(WintError::BadCommand(_)))
+        ));
+        assert!(matches!(
+            parse_move(":move ab", 2),
+            Some(Err(WintError::BadCommand(_)))
+        ));
+        assert!(matches!(
+            parse_move(":move c", 2),
+            Some(Err(WintError::UnknownHint('c')))
+        ));
+    }
+
+    #[test]
+    fn completes_nicks() {
+        let nicks = vec![
+            String::from("tex"),
+            String::from("term"),
+            String::from("pdf"),
+        ];
+        assert_eq!(complete("cpdf at", &nicks), vec!["cpdf atex", "cpdf aterm"]);
+        assert_eq!(complete("ap", &nicks), vec!["apdf"]);
+        assert!(complete("a>2", &nicks).is_empty());
+        assert!(complete("", &nicks).is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::tilings::Rect;
+use crate::WintError;
+
+/// The part of the width which `:tall` gives to the master window, unless `tilings.xml` sets `master-ratio`
+pub const DEFAULT_MASTER_RATIO: f64 = 0.6;
+
+/// The grid of the cells in `@1:0`, unless `tilings.xml` sets `grid`
+pub const DEFAULT_GRID: &str = "2x2";
+
+/// How far each window of `:cascade` is from the previous one, at most
+const CASCADE_STEP: u32 = 32;
+
+/// A layout computed for any number of windows, written `:tall`, `:cols3`, `:grid` or `:cascade` in a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generated {
+    /// the first window (the master) on the left, and the others stacked on the right
+    Tall,
+    /// side by side, in this many columns (or one column per window); extra windows are stacked in the columns
+    Columns(Option<usize>),
+    /// in rows and columns, as many columns as rows (or one more)
+    Grid,
+    /// overlapping, each window a step lower and to the right of the previous one
+    Cascade,
+}
+
+impl Generated {
+    /// Parses the name of a generated layout, without the `:`
+    pub fn parse(name: &str) -> Result<Generated, WintError> {
+        let unknown = || WintError::UnknownGenerated(name.to_string());
+        match name {
+            "tall" => Ok(Generated::Tall),
+            "grid" => Ok(Generated::Grid),
+            "cascade" => Ok(Generated::Cascade),
+            "cols" => Ok(Generated::Columns(None)),
+            _ => match name.strip_prefix("cols").map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => Ok(Generated::Columns(Some(n))),
+                _ => Err(unknown()),
+            },
+        }
+    }
+
+    /// The rectangles of `n` windows in `area`, with `gap` pixels between adjacent windows
+    pub fn rects(&self, n: usize, area: &Rect, gap: u32, master_ratio: f64) -> Vec<Rect> {
+        if n == 0 {
+            return vec![];
+        }
+        match self {
+            Generated::Tall if n > 1 => {
+                // in an area too narrow for the gap, the windows get at least a pixel each
+                let usable = area.width.saturating_sub(gap);
+                let master =
+                    ((usable as f64 * master_ratio).round() as u32).clamp(1, usable.max(2) - 1);
+                let width = usable.saturating_sub(master).max(1);
+                let stack = Rect {
+                    // and the stack stays in the area, over the gap if need be
+                    x: (area.x + (master + gap) as i32).min(area.right() - width as i32),
+                    y: area.y,
+                    width,
+                    height: area.height,
+                };
+                let mut rects = vec![Rect {
+                    width: master,
+                    height: area.height.max(1),
+                    ..*area
+                }];
+                rects.extend(stacks(&stack, &[n - 1], gap));
+                rects
+            }
+            Generated::Tall => vec![*area],
+            Generated::Columns(columns) => {
+                let columns = columns.unwrap_or(n).min(n);
+                let counts: Vec<usize> = (0..columns)
+                    .map(|i| n / columns + usize::from(i < n % columns))
+                    .collect();
+                stacks(area, &counts, gap)
+            }
+            Generated::Grid => {
+                let columns = (n as f64).sqrt().ceil() as usize;
+                let rows = n.div_ceil(columns);
+                // the last row has the windows which are left, made wider
+                let counts: Vec<usize> = (0..rows)
+                    .map(|r| {
+                        if r + 1 < rows {
+                            columns
+                        } else {
+                            n - columns * (rows - 1)
+                        }
+                    })
+                    .collect();
+                stacks(&transposed(area), &counts, gap)
+                    .iter()
+                    .map(transposed)
+                    .collect()
+            }
+            Generated::Cascade => {
+                let steps = (n - 1) as u32;
+                let step = (area.width.min(area.height) / 2)
+                    .checked_div(steps)
+                    .map_or(0, |s| s.min(CASCADE_STEP));
+                (0..n as u32)
+                    .map(|i| Rect {
+                        x: area.x + (i * step) as i32,
+                        y: area.y + (i * step) as i32,
+                        width: area.width.saturating_sub(steps * step).max(1),
+                        height: area.height.saturating_sub(steps * step).max(1),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A block of cells of a grid dividing the work area, e.g. `@0:0-1:1` or `@2x2/tl` in a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cells {
+    pub columns: usize,
+    pub rows: usize,
+    /// the column and the row of the top left cell
+    pub from: (usize, usize),
+    /// the column and the row of the bottom right cell
+    pub to: (usize, usize),
+}
+
+/// Parses `COLUMNSxROWS`
+fn parse_grid(grid: &str) -> Option<(usize, usize)> {
+    let (columns, rows) = grid.split_once('x')?;
+    match (columns.parse::<usize>().ok()?, rows.parse::<usize>().ok()?) {
+        (0, _) | (_, 0) => None,
+        size => Some(size),
+    }
+}
+
+/// Parses `COLUMN:ROW`, counting from 0
+fn parse_cell(cell: &str) -> Option<(usize, usize)> {
+    let (column, row) = cell.split_once(':')?;
+    Some((column.parse().ok()?, row.parse().ok()?))
+}
+
+impl Cells {
+    /// Parses what follows the `@`: an optional grid `COLUMNSxROWS/` (otherwise `default_grid`), then either
+    /// a cell `COLUMN:ROW`, a block of cells `COLUMN:ROW-COLUMN:ROW` between two corners, or one of the names
+    /// `tl`, `t`, `tr`, `l`, `c`, `r`, `bl`, `b` and `br` (a corner cell, a whole row or column at an edge,
+    /// or the middle cells)
+    pub fn parse(spec: &str, default_grid: &str) -> Result<Cells, WintError> {
+        let bad = || WintError::BadCell(spec.to_string());
+        let (grid, cells) = spec.split_once('/').unwrap_or((default_grid, spec));
+        let (columns, rows) = parse_grid(grid).ok_or_else(bad)?;
+        let (last_column, last_row) = (columns - 1, rows - 1);
+        let (from, to) = match cells {
+            "tl" => ((0, 0), (0, 0)),
+            "t" => ((0, 0), (last_column, 0)),
+            "tr" => ((last_column, 0), (last_column, 0)),
+            "l" => ((0, 0), (0, last_row)),
+            "c" => ((last_column / 2, last_row / 2), (columns / 2, rows / 2)),
+            "r" => ((last_column, 0), (last_column, last_row)),
+            "bl" => ((0, last_row), (0, last_row)),
+            "b" => ((0, last_row), (last_column, last_row)),
+            "br" => ((last_column, last_row), (last_column, last_row)),
+            _ => {
+                let (a, b) = cells.split_once('-').unwrap_or((cells, cells));
+                let (a, b) = (
+                    parse_cell(a).ok_or_else(bad)?,
+                    parse_cell(b).ok_or_else(bad)?,
+                );
+                ((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1)))
+            }
+        };
+        if to.0 >= columns || to.1 >= rows {
+            return Err(bad());
+        }
+        Ok(Cells {
+            columns,
+            rows,
+            from,
+            to,
+        })
+    }
+
+    /// The rectangle covered by the cells, when the grid divides `area` with `gap` pixels between the cells
+    pub fn rect(&self, area: &Rect, gap: u32) -> Rect {
+        let xs = split(area.x, area.width, self.columns, gap);
+        let ys = split(area.y, area.height, self.rows, gap);
+        let (left, right) = (xs[self.from.0], xs[self.to.0]);
+        let (top, bottom) = (ys[self.from.1], ys[self.to.1]);
+        Rect {
+            x: left.0,
+            y: top.0,
+            width: (right.0 - left.0) as u32 + right.1,
+            height: (bottom.0 - top.0) as u32 + bottom.1,
+        }
+    }
+}
+
+/// Divides a span into `parts` of (almost) the same length, `gap` apart; gives the start and the length of each
+/// (in a span too short for the gaps, the parts overlap at its end)
+fn split(start: i32, len: u32, parts: usize, gap: u32) -> Vec<(i32, u32)> {
+    let parts32 = parts as u32;
+    let total = len.saturating_sub(gap.saturating_mul(parts32.saturating_sub(1)));
+    let (base, extra) = (total / parts32, total % parts32);
+    let end = start + len.max(1) as i32;
+    let mut pos = start;
+    (0..parts32)
+        .map(|i| {
+            let l = base + u32::from(i < extra);
+            let part = (pos.min(end - l.max(1) as i32), l.max(1));
+            pos += (l + gap) as i32;
+            part
+        })
+        .collect()
+}
+
+/// Columns across `area`, one for each of the `counts`, and in each column that many windows one above the other
+fn stacks(area: &Rect, counts: &[usize], gap: u32) -> Vec<Rect> {
+    let mut rects = vec![];
+    for (&(x, width), &count) in split(area.x, area.width, counts.len(), gap)
+        .iter()
+        .zip(counts)
+    {
+        for (y, height) in split(area.y, area.height, count, gap) {
+            rects.push(Rect {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+    }
+    rects
+}
+
+/// The rectangle mirrored along the diagonal, so that columns become rows
+fn transposed(rect: &Rect) -> Rect {
+    Rect {
+        x: rect.y,
+        y: rect.x,
+        width: rect.height,
+        height: rect.width,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect::new(0, 0, 1000, 800);
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Generated::parse("tall").unwrap(), Generated::Tall);
+        assert_eq!(Generated::parse("cols").unwrap(), Generated::Columns(None));
+        assert_eq!(
+            Generated::parse("cols3").unwrap(),
+            Generated::Columns(Some(3))
+        );
+        for bad in ["cols0", "colsx", "spiral", ""] {
+            assert!(Generated::parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn tall() {
+        assert_eq!(
+            Generated::Tall.rects(3, &AREA, 10, 0.6),
+            vec![
+                Rect::new(0, 0, 594, 800),
+                Rect::new(604, 0, 396, 395),
+                Rect::new(604, 405, 396, 395)
+            ]
+        );
+        assert_eq!(Generated::Tall.rects(1, &AREA, 10, 0.6), vec![AREA]);
+        assert!(Generated::Tall.rects(0, &AREA, 10, 0.6).is_empty());
+    }
+
+    #[test]
+    fn columns_and_grid() {
+        assert_eq!(
+            Generated::Columns(Some(2)).rects(3, &AREA, 0, 0.6),
+            vec![
+                Rect::new(0, 0, 500, 400),
+                Rect::new(0, 400, 500, 400),
+                Rect::new(500, 0, 500, 800)
+            ]
+        );
+        assert_eq!(
+            Generated::Grid.rects(3, &AREA, 0, 0.6),
+            vec![
+                Rect::new(0, 0, 500, 400),
+                Rect::new(500, 0, 500, 400),
+                Rect::new(0, 400, 1000, 400)
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade() {
+        assert_eq!(
+            Generated::Cascade.rects(3, &AREA, 0, 0.6),
+            vec![
+                Rect::new(0, 0, 936, 736),
+                Rect::new(32, 32, 936, 736),
+                Rect::new(64, 64, 936, 736)
+            ]
+        );
+    }
+
+    #[test]
+    fn tiny_areas_give_tiny_windows() {
+        let tiny = Rect::new(0, 0, 5, 5);
+        for layout in [
+            Generated::Tall,
+            Generated::Columns(None),
+            Generated::Grid,
+            Generated::Cascade,
+        ] {
+            for n in 1..6 {
+                for r in layout.rects(n, &tiny, 10, 0.6) {
+                    assert!(
+                        r.width >= 1
+                            && r.height >= 1
+                            && r.x >= tiny.x
+                            && r.y >= tiny.y
+                            && r.right() <= tiny.right()
+                            && r.bottom() <= tiny.bottom(),
+                        "{:?} {} {:?}",
+                        layout,
+                        n,
+                        r
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parses_cells() {
+        assert_eq!(
+            Cells::parse("0:0-1:1", "3x2").unwrap(),
+            Cells {
+                columns: 3,
+                rows: 2,
+                from: (0, 0),
+                to: (1, 1)
+            }
+        );
+        let corner = Cells::parse("2x2/br", DEFAULT_GRID).unwrap();
+        assert_eq!((corner.from, corner.to), ((1, 1), (1, 1)));
+        let middle = Cells::parse("3x3/c", DEFAULT_GRID).unwrap();
+        assert_eq!((middle.from, middle.to), ((1, 1), (1, 1)));
+        for bad in ["2:0", "0x2/0:0", "0:0-", "x", "2x2/q"] {
+            assert!(Cells::parse(bad, DEFAULT_GRID).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn cell_rects() {
+        let cells = Cells::parse("3x2/1:0-2:1", DEFAULT_GRID).unwrap();
+        assert_eq!(
+            cells.rect(&Rect::new(0, 0, 920, 810), 10),
+            Rect::new(310, 0, 610, 810)
+        );
+        let cell = Cells::parse("tl", DEFAULT_GRID).unwrap();
+        assert_eq!(cell.rect(&AREA, 0), Rect::new(0, 0, 500, 400));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::tilings::Rect;
+use std::path::Path;
+
+/// How many tiling operations `wint --undo` can take back
+pub const HISTORY_LENGTH: usize = 10;
+
+/// A window as it was before `wint` tiled it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedWindow {
+    pub id: u32,
+    /// the outer frame of the window
+    pub rect: Rect,
+    pub desktop: u32,
+    pub maximized_vert: bool,
+    pub maximized_horz: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+    pub shaded: bool,
+    pub above: bool,
+    pub below: bool,
+}
+
+impl SavedWindow {
+    /// One word of the history file: `id:x,y,width,height:desktop:states`, where the states are
+    /// `v` and `h` for maximized vertically and horizontally, `f` for fullscreen, `s` for sticky, `u` for shaded
+    /// (rolled up), `a` and `b` for above and below the other windows, or `-` for none of them
+    pub fn to_word(&self) -> String {
+        let mut states = String::new();
+        for (on, c) in [
+            (self.maximized_vert, 'v'),
+            (self.maximized_horz, 'h'),
+            (self.fullscreen, 'f'),
+            (self.sticky, 's'),
+            (self.shaded, 'u'),
+            (self.above, 'a'),
+            (self.below, 'b'),
+        ] {
+            if on {
+                states.push(c);
+            }
+        }
+        if states.is_empty() {
+            states.push('-');
+        }
+        format!(
+            "{}:{},{},{},{}:{}:{}",
+            self.id,
+            self.rect.x,
+            self.rect.y,
+            self.rect.width,
+            self.rect.height,
+            self.desktop,
+            states
+        )
+    }
+
+    pub fn from_word(word: &str) -> Option<SavedWindow> {
+        let parts: Vec<&str> = word.split(':').collect();
+        if parts.len() != 4 {
+            return None;
+        }
+        let geometry: Vec<&str> = parts[1].split(',').collect();
+        if geometry.len() != 4 {
+            return None;
+        }
+        Some(SavedWindow {
+            id: parts[0].parse().ok()?,
+            rect: Rect {
+                x: geometry[0].parse().ok()?,
+                y: geometry[1].parse().ok()?,
+                width: geometry[2].parse().ok()?,
+                height: geometry[3].parse().ok()?,
+            },
+            desktop: parts[2].parse().ok()?,
+            maximized_vert: parts[3].contains('v'),
+            maximized_horz: parts[3].contains('h'),
+            fullscreen: parts[3].contains('f'),
+            sticky: parts[3].contains('s'),
+            shaded: parts[3].contains('u'),
+            above: parts[3].contains('a'),
+            below: parts[3].contains('b'),
+        })
+    }
+}
+
+/// The last tiling operations, oldest first; the history file has one line per operation
+#[derive(Debug, Default)]
+pub struct History {
+    pub operations: Vec<Vec<SavedWindow>>,
+}
+
+impl History {
+    /// Reads the history file; a missing file is an empty history, and unreadable lines are skipped
+    pub fn read(path: &Path) -> History {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let operations = text
+            .lines()
+            .map(|line| {
+                line.split_whitespace()
+                    .filter_map(SavedWindow::from_word)
+                    .collect::<Vec<SavedWindow>>()
+            })
+            .filter(|op| !op.is_empty())
+            .collect();
+        History { operations }
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let lines: Vec<String> = self
+            .operations
+            .iter()
+            .map(|op| {
+                op.iter()
+                    .map(|w| w.to_word())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Adds an operation, forgetting the oldest ones beyond `HISTORY_LENGTH`
+    pub fn push(&mut self, operation: Vec<SavedWindow>) {
+        if operation.is_empty() {
+            return;
+        }
+        self.operations.push(operation);
+        let excess = self.operations.len().saturating_sub(HISTORY_LENGTH);
+        self.operations.drain(..excess);
+    }
+
+    /// Takes the last operation
+    pub fn pop(&mut self) -> Option<Vec<SavedWindow>> {
+        self.operations.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(id: u32) -> SavedWindow {
+        SavedWindow {
+            id,
+            rect: Rect::new(-10, 20, 300, 400),
+            desktop: 2,
+            maximized_vert: false,
+            maximized_horz: false,
+            fullscreen: false,
+            sticky: false,
+            shaded: false,
+            above: false,
+            below: false,
+        }
+    }
+
+    #[test]
+    fn words_round_trip() {
+        let plain = saved(1);
+        assert_eq!(plain.to_word(), "1:-10,20,300,400:2:-");
+        assert_eq!(SavedWindow::from_word(&plain.to_word()), Some(plain));
+        let states = SavedWindow {
+            maximized_vert: true,
+            maximized_horz: true,
+            sticky: true,
+            below: true,
+            ..saved(2)
+        };
+        assert_eq!(states.to_word(), "2:-10,20,300,400:2:vhsb");
+        assert_eq!(SavedWindow::from_word(&states.to_word()), Some(states));
+    }
+
+    #[test]
+    fn rejects_bad_words() {
+        for word in [
+            "",
+            "1:2:3",
+            "1:0,0,10:0:-",
+            "x:0,0,10,10:0:-",
+            "1:0,0,-10,10:0:-",
+        ] {
+            assert_eq!(SavedWindow::from_word(word), None, "{:?}", word);
+        }
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let path = std::env::temp_dir().join(format!("wint-test-history-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "1:0,0,10,10:0:- garbage 2:0,0,10,10:0:v\nnot a window\n\n3:5,5,10,10:1:-\n",
+        )
+        .unwrap();
+        let history = History::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        let ids: Vec<Vec<u32>> = history
+            .operations
+            .iter()
+            .map(|op| op.iter().map(|w| w.id).collect())
+            .collect();
+        assert_eq!(ids, vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let path =
+            std::env::temp_dir().join(format!("wint-test-history-rw-{}", std::process::id()));
+        let mut history = History::default();
+        history.push(vec![saved(1), saved(2)]);
+        history.push(vec![saved(3)]);
+        history.write(&path).unwrap();
+        let read = History::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.operations, history.operations);
+        assert!(History::read(&path).operations.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_operations() {
+        let mut history = History::default();
+        history.push(vec![]);
+        assert!(history.operations.is_empty());
+        for id in 0..HISTORY_LENGTH as u32 + 3 {
+            history.push(vec![saved(id)]);
+        }
+        assert_eq!(history.operations.len(), HISTORY_LENGTH);
+        assert_eq!(history.operations[0][0].id, 3);
+        assert_eq!(history.pop().unwrap()[0].id, HISTORY_LENGTH as u32 + 2);
+        assert_eq!(history.operations.len(), HISTORY_LENGTH - 1);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::tilings::Rect;
+
+/// The smallest width and height to which a window can be shrunk with the keyboard
+const MIN_SIZE: u32 = 32;
+
+/// An arrow key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// How far the arrow keys move and resize a window, in pixels, and from how far it snaps to an edge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Steps {
+    pub move_step: u32,
+    pub resize_step: u32,
+    pub snap: u32,
+}
+
+/// A window being moved and resized with the arrow keys, in `wint :move a`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMove {
+    /// the outer frame of the window, where it would go
+    pub rect: Rect,
+    /// the work area of the monitor, which the window does not leave
+    area: Rect,
+    steps: Steps,
+    /// the vertical edges (x coordinates) to stop at: of the work area, and of the other windows
+    xs: Vec<i32>,
+    /// the horizontal edges (y coordinates) to stop at
+    ys: Vec<i32>,
+}
+
+impl KeyboardMove {
+    /// Starts from `rect`, with the edges of the work area `area` and of the `others` windows to snap to
+    pub fn new(rect: Rect, area: &Rect, others: &[Rect], steps: Steps) -> KeyboardMove {
+        let mut xs = vec![area.x, area.right()];
+        let mut ys = vec![area.y, area.bottom()];
+        for r in others {
+            xs.extend([r.x, r.right()]);
+            ys.extend([r.y, r.bottom()]);
+        }
+        KeyboardMove {
+            rect,
+            area: *area,
+            steps,
+            xs,
+            ys,
+        }
+    }
+
+    /// Moves the window a step; it stops at the first edge on the way, or snaps to an edge just beyond the step,
+    /// and stays in the work area
+    pub fn move_by(&mut self, direction: Direction) {
+        let r = self.rect;
+        let step = self.steps.move_step;
+        match direction {
+            Direction::Left | Direction::Right => {
+                let forward = direction == Direction::Right;
+                self.rect.x += advance(&[r.x, r.right()], forward, step, self.steps.snap, &self.xs);
+            }
+            Direction::Up | Direction::Down => {
+                let forward = direction == Direction::Down;
+                self.rect.y +=
+                    advance(&[r.y, r.bottom()], forward, step, self.steps.snap, &self.ys);
+            }
+        }
+        let area = self.area;
+        self.rect.x = clamp_start(self.rect.x, self.rect.width, area.x, area.right());
+        self.rect.y = clamp_start(self.rect.y, self.rect.height, area.y, area.bottom());
+    }
+
+    /// Moves the right edge (with Left and Right) or the bottom edge (with Up and Down) a step, stopping
+    /// and snapping like `move_by`; the edge does not go beyond the work area
+    pub fn resize_by(&mut self, direction: Direction) {
+        let r = self.rect;
+        let step = self.steps.resize_step;
+        match direction {
+            Direction::Left | Direction::Right => {
+                let forward = direction == Direction::Right;
+                let right =
+                    r.right() + advance(&[r.right()], forward, step, self.steps.snap, &self.xs);
+                let right = right.min(self.area.right());
+                self.rect.width = (right - r.x).max(MIN_SIZE as i32) as u32;
+            }
+            Direction::Up | Direction::Down => {
+                let forward = direction == Direction::Down;
+                let bottom =
+                    r.bottom() + advance(&[r.bottom()], forward, step, self.steps.snap, &self.ys);
+                let bottom = bottom.min(self.area.bottom());
+                self.rect.height = (bottom - r.y).max(MIN_SIZE as i32) as u32;
+            }
+        }
+    }
+}
+
+/// The start of a span of `len` pixels at `start`, moved into the span from `area_start` to `area_end`
+/// (to `area_start` if it does not fit)
+fn clamp_start(start: i32, len: u32, area_start: i32, area_end: i32) -> i32 {
+    start.min(area_end - len as i32).max(area_start)
+}
+
+/// How far to move edges at `positions` along one axis, forward (right or down) or backward: by `step`,
+/// unless one of them meets one of the `lines` on the way, or within `snap` pixels beyond the step
+fn advance(positions: &[i32], forward: bool, step: u32, snap: u32, lines: &[i32]) -> i32 {
+    let reach = (step + snap) as i32;
+    let distance = positions
+        .iter()
+        .flat_map(|p| {
+            lines
+                .iter()
+                .map(move |l| if forward { l - p } else { p - l })
+        })
+        .filter(|d| *d > 0 && *d <= reach)
+        .min()
+        .unwrap_or(step as i32);
+    if forward {
+        distance
+    } else {
+        -distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect::new(0, 0, 1000, 800);
+
+    const STEPS: Steps = Steps {
+        move_step: 20,
+        resize_step: 20,
+        snap: 10,
+    };
+
+    #[test]
+    fn moves_by_steps() {
+        let mut k = KeyboardMove::new(Rect::new(100, 100, 300, 200), &AREA, &[], STEPS);
+        k.move_by(Direction::Right);
+        k.move_by(Direction::Down);
+        assert_eq!(k.rect, Rect::new(120, 120, 300, 200));
+        k.move_by(Direction::Left);
+        k.move_by(Direction::Up);
+        assert_eq!(k.rect, Rect::new(100, 100, 300, 200));
+    }
+
+    #[test]
+    fn snaps_to_edges_just_beyond_the_step() {
+        let mut k = KeyboardMove::new(Rect::new(25, 100, 300, 200), &AREA, &[], STEPS);
+        k.move_by(Direction::Left);
+        assert_eq!(k.rect.x, 0);
+    }
+
+    #[test]
+    fn stops_at_other_windows() {
+        let other = Rect::new(500, 0, 500, 400);
+        let mut k = KeyboardMove::new(Rect::new(190, 100, 300, 200), &AREA, &[other], STEPS);
+        k.move_by(Direction::Right);
+        assert_eq!(k.rect.right(), 500);
+        // and goes on from there
+        k.move_by(Direction::Right);
+        assert_eq!(k.rect.right(), 520);
+    }
+
+    #[test]
+    fn stays_in_the_work_area() {
+        let mut k = KeyboardMove::new(Rect::new(5, 5, 300, 200), &AREA, &[], STEPS);
+        for _ in 0..5 {
+            k.move_by(Direction::Left);
+            k.move_by(Direction::Up);
+        }
+        assert_eq!(k.rect, Rect::new(0, 0, 300, 200));
+        for _ in 0..100 {
+            k.move_by(Direction::Right);
+            k.move_by(Direction::Down);
+        }
+        assert_eq!(k.rect, Rect::new(700, 600, 300, 200));
+    }
+
+    #[test]
+    fn resizes_within_limits() {
+        let mut k = KeyboardMove::new(Rect::new(100, 100, 300, 200), &AREA, &[], STEPS);
+        k.resize_by(Direction::Right);
+        k.resize_by(Direction::Down);
+        assert_eq!(k.rect, Rect::new(100, 100, 320, 220));
+        for _ in 0..100 {
+            k.resize_by(Direction::Right);
+            k.resize_by(Direction::Up);
+        }
+        assert_eq!(k.rect, Rect::new(100, 100, 900, MIN_SIZE));
+    }
+}
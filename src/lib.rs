@@ -5,14 +5,32 @@ extern crate serde_xml_rs;
 extern crate xcb_wm;
 
 use dirs::home_dir;
+use gtk::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use gtk::glib;
 use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use xcb::x::Window;
+use xcb::Xid;
 use xcb_wm::{ewmh, icccm};
 
+pub mod capture;
+pub mod command;
+pub mod generated;
+pub mod history;
+pub mod keyboard;
+pub mod session;
+pub mod size_hints;
+pub mod tilings;
+
+use history::SavedWindow;
+use session::SessionWindow;
+use size_hints::SizeHints;
+use tilings::Rect;
+
 #[derive(Debug)]
 pub enum WintError {
     //Errors from external libs:
@@ -20,6 +38,21 @@ pub enum WintError {
     NoConfigFile(std::io::Error),
     XCBConnError(xcb::ConnError),
     XCBError(xcb::Error),
+    //Our own errors:
+    BadGeometry(String),
+    UnknownMonitor(String),
+    UnknownLayout(String),
+    UnknownWindow(String),
+    BadCommand(String),
+    UnknownHint(char),
+    UnknownNick(String),
+    DuplicateWindow(char),
+    NothingToUndo,
+    CannotCapture(String),
+    UnknownSession(String),
+    UnknownGenerated(String),
+    BadCell(String),
+    NoRuntimeDir,
 }
 
 impl std::fmt::Display for WintError {
@@ -29,6 +62,28 @@ impl std::fmt::Display for WintError {
             WintError::NoConfigFile(ref err) => err.fmt(f),
             WintError::XCBConnError(ref err) => err.fmt(f),
             WintError::XCBError(ref err) => err.fmt(f),
+            WintError::BadGeometry(ref msg) => write!(f, "{}", msg),
+            WintError::UnknownMonitor(ref name) => write!(f, "no monitor {:?}", name),
+            WintError::UnknownLayout(ref name) => write!(f, "no layout {:?} in tilings.xml", name),
+            WintError::UnknownWindow(ref id) => write!(f, "no managed window with id {}", id),
+            WintError::BadCommand(ref msg) => write!(f, "{}", msg),
+            WintError::UnknownHint(hint) => write!(f, "no window with the charhint {:?}", hint),
+            WintError::UnknownNick(ref nick) => write!(f, "no nick {:?} in tilings.xml", nick),
+            WintError::DuplicateWindow(hint) => write!(f, "the window {} is tiled twice", hint),
+            WintError::NothingToUndo => write!(f, "nothing to undo"),
+            WintError::CannotCapture(ref msg) => write!(f, "could not capture: {}", msg),
+            WintError::UnknownSession(ref name) => write!(f, "no saved session {:?}", name),
+            WintError::UnknownGenerated(ref name) => write!(
+                f,
+                "no generated layout :{} (there are :tall, :cols, :cols2, ..., :grid and :cascade)",
+                name
+            ),
+            WintError::BadCell(ref spec) => write!(
+                f,
+                "bad grid cell @{} (should be like @1:0, @0:0-1:1 or @2x2/tl)",
+                spec
+            ),
+            WintError::NoRuntimeDir => write!(f, "system does not have XDG_RUNTIME_DIR; please use custom <tmpfile><custom>...</custom></tmpfile> or <tmpfile><in_tmp/></custom> option in config"),
         }
     }
 }
@@ -64,6 +119,13 @@ pub struct BlacklistedItems {
     pub item: Vec<BlacklistedItem>,
 }
 
+impl BlacklistedItems {
+    /// Whether windows of this class are left out
+    pub fn contains(&self, class: &str) -> bool {
+        self.item.iter().any(|i| i.class == class)
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq)]
 pub enum TMPFile {
     #[serde(rename = "in_xdg_runtime")]
@@ -74,6 +136,24 @@ pub enum TMPFile {
     Custom(String),
 }
 
+/// Where the `tmpfile` of the configuration is
+pub fn tmpfile_path(tmpfile: &TMPFile) -> String {
+    find_tmpfile_path(tmpfile).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Where the `tmpfile` of the configuration is, or `NoRuntimeDir` if it should be in `XDG_RUNTIME_DIR`
+/// and that is not set
+pub fn find_tmpfile_path(tmpfile: &TMPFile) -> Result<String, WintError> {
+    match tmpfile {
+        TMPFile::Custom(x) => Ok(x.to_string()),
+        TMPFile::InXdgRuntime => match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(x) => Ok(format!("{}/winterreise", x)),
+            Err(_) => Err(WintError::NoRuntimeDir),
+        },
+        TMPFile::InTmp => Ok(String::from("/tmp/winterreise")),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename = "configuration")]
 pub struct Config {
@@ -82,19 +162,83 @@ pub struct Config {
     pub space_between_buttons: i32,
     pub maxwidth: usize,
     pub blacklist: BlacklistedItems,
+    #[serde(rename = "wrapDesktops", default)]
+    pub wrap_desktops: bool,
+    #[serde(rename = "showIcons", default)]
+    pub show_icons: bool,
+    #[serde(rename = "iconSize", default = "default_icon_size")]
+    pub icon_size: i32,
+    #[serde(rename = "showThumbnails", default)]
+    pub show_thumbnails: bool,
+    #[serde(rename = "thumbnailSize", default = "default_thumbnail_size")]
+    pub thumbnail_size: i32,
+    #[serde(rename = "urgentFirst", default)]
+    pub urgent_first: bool,
+    #[serde(rename = "moveStep", default = "default_keyboard_step")]
+    pub move_step: u32,
+    #[serde(rename = "resizeStep", default = "default_keyboard_step")]
+    pub resize_step: u32,
+    #[serde(rename = "snapDistance", default = "default_snap_distance")]
+    pub snap_distance: u32,
+}
+
+fn default_icon_size() -> i32 {
+    24
+}
+
+fn default_thumbnail_size() -> i32 {
+    160
+}
+
+fn default_keyboard_step() -> u32 {
+    20
 }
 
+fn default_snap_distance() -> u32 {
+    10
+}
+
+/// How `make_vbox` draws the rows
+pub struct RowStyle {
+    pub space_between_buttons: i32,
+    pub maxlen: usize,
+    pub icon_size: Option<i32>,
+    pub thumbnail_size: Option<i32>,
+    pub urgent_first: bool,
+}
+
+impl RowStyle {
+    pub fn from_config(conf: &Config) -> RowStyle {
+        RowStyle {
+            space_between_buttons: conf.space_between_buttons,
+            maxlen: conf.maxwidth,
+            icon_size: if conf.show_icons {
+                Some(conf.icon_size)
+            } else {
+                None
+            },
+            thumbnail_size: if conf.show_thumbnails {
+                Some(conf.thumbnail_size)
+            } else {
+                None
+            },
+            urgent_first: conf.urgent_first,
+        }
+    }
+}
+
+/// `_NET_WM_DESKTOP` value of windows shown on all desktops
+pub const STICKY_DESKTOP: u32 = 0xFFFFFFFF;
+
+/// A client window: its id, desktop, title, `res_class`, and whether it demands attention
+pub type WinInfo = (Window, u32, String, String, bool);
+
 pub struct WM {
     pub wins: Rc<Vec<(u32, u32, String, String)>>,
     pub desktop: u32,
 }
 
-pub fn get_wm_data() -> (
-    Rc<Vec<(Window, u32, String, String)>>,
-    Rc<String>,
-    u32,
-    Window,
-) {
+pub fn get_wm_data() -> (Rc<Vec<WinInfo>>, Rc<String>, u32, Window) {
     let (xcb_conn, _screen_id) =
         xcb::Connection::connect(None).expect("XCB connection failed in get_wm_data");
     let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
@@ -123,7 +267,7 @@ pub fn get_wm_data() -> (
         .expect("Failed to get client list");
     let clients = clients_repl.clients;
 
-    let wins: Rc<Vec<(Window, u32, String, String)>> = Rc::new(
+    let wins: Rc<Vec<WinInfo>> = Rc::new(
         clients
             .iter()
             .map(|w| {
@@ -145,7 +289,19 @@ pub fn get_wm_data() -> (
                     .wait_for_reply(wmclass_cookie)
                     .expect("Failed to get window class");
                 let class = wmclass_repl.class;
-                (*w, dtop, nm, class)
+                let state_req = ewmh::proto::GetWmState(*w);
+                let state_cookie = ewmh_conn.send_request(&state_req);
+                let demands_attention = match ewmh_conn.wait_for_reply(state_cookie) {
+                    Ok(repl) => repl
+                        .states
+                        .contains(&ewmh_conn.atoms._NET_WM_STATE_DEMANDS_ATTENTION),
+                    Err(_) => false,
+                };
+                // the ICCCM urgency hint is bit 8 of the flags in WM_HINTS
+                let urgency_hint = get_cardinals(&xcb_conn, *w, xcb::x::ATOM_WM_HINTS)
+                    .first()
+                    .is_some_and(|flags| flags & (1 << 8) != 0);
+                (*w, dtop, nm, class, demands_attention || urgency_hint)
             })
             .collect(),
     );
@@ -177,15 +333,1045 @@ pub fn abbreviate(x: String, maxlen: usize) -> String {
         );
     }
 }
+/// The window which most recently demanded attention; as we do not know when that happened,
+/// we take the newest one in `_NET_CLIENT_LIST` (which is in the initial mapping order). Windows
+/// in the `blacklist` are not listed, so they are not jumped to either
+pub fn most_recent_urgent(wins: &Rc<Vec<WinInfo>>, blacklist: &BlacklistedItems) -> Option<Window> {
+    shown_windows(wins, blacklist)
+        .iter()
+        .rev()
+        .find(|win| win.4)
+        .map(|win| win.0)
+}
+
+pub fn get_desktop_names() -> Rc<Vec<String>> {
+    let (xcb_conn, _screen_id) =
+        xcb::Connection::connect(None).expect("XCB connection failed in get_desktop_names");
+    let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+    let number_req = ewmh::proto::GetNumberOfDesktops;
+    let number_cookie = ewmh_conn.send_request(&number_req);
+    let number_repl = ewmh_conn
+        .wait_for_reply(number_cookie)
+        .expect("Failed to get number of desktops");
+    let names_req = ewmh::proto::GetDesktopNames;
+    let names_cookie = ewmh_conn.send_request(&names_req);
+    let mut names = match ewmh_conn.wait_for_reply(names_cookie) {
+        Ok(repl) => repl.names,
+        Err(_) => vec![],
+    };
+    // _NET_DESKTOP_NAMES may list fewer (or more) names than there are desktops
+    names.resize(number_repl.desktops as usize, String::new());
+    return Rc::new(names);
+}
+
+/// Reads a `CARDINAL[]/32` (or any other 32-bit) property; empty if the property is not set
+pub fn get_cardinals(
+    xcb_conn: &xcb::Connection,
+    window: Window,
+    property: xcb::x::Atom,
+) -> Vec<u32> {
+    let cookie = xcb_conn.send_request(&xcb::x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type: xcb::x::ATOM_ANY,
+        long_offset: 0,
+        long_length: u32::MAX,
+    });
+    match xcb_conn.wait_for_reply(cookie) {
+        Ok(repl) if repl.format() == 32 => repl.value::<u32>().to_vec(),
+        _ => vec![],
+    }
+}
+
+/// The part of the screen not covered by panels on `desktop`: taken from `_NET_WORKAREA`,
+/// or else computed from the struts of the windows
+pub fn get_workarea(desktop: u32) -> Rect {
+    let (xcb_conn, screen_id) =
+        xcb::Connection::connect(None).expect("XCB connection failed in get_workarea");
+    let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+    let screen = xcb_conn
+        .get_setup()
+        .roots()
+        .nth(screen_id as usize)
+        .expect("no screen");
+    let root = screen.root();
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: screen.width_in_pixels() as u32,
+        height: screen.height_in_pixels() as u32,
+    };
+    let workareas = get_cardinals(&xcb_conn, root, ewmh_conn.atoms._NET_WORKAREA);
+    let i = if desktop == STICKY_DESKTOP {
+        0
+    } else {
+        desktop as usize
+    };
+    if let Some(wa) = workareas
+        .chunks_exact(4)
+        .nth(i)
+        .or(workareas.chunks_exact(4).next())
+    {
+        if wa[2] > 0 && wa[3] > 0 {
+            return Rect {
+                x: wa[0] as i32,
+                y: wa[1] as i32,
+                width: wa[2],
+                height: wa[3],
+            };
+        }
+    }
+    // no _NET_WORKAREA: subtract the struts of the panels (which may or may not be managed windows)
+    let mut candidates =
+        match xcb_conn.wait_for_reply(xcb_conn.send_request(&xcb::x::QueryTree { window: root })) {
+            Ok(tree) => tree.children().to_vec(),
+            Err(_) => vec![],
+        };
+    if let Ok(clients) =
+        ewmh_conn.wait_for_reply(ewmh_conn.send_request(&ewmh::proto::GetClientList))
+    {
+        candidates.extend(clients.clients);
+    }
+    let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+    for w in candidates {
+        let mut strut = get_cardinals(&xcb_conn, w, ewmh_conn.atoms._NET_WM_STRUT_PARTIAL);
+        if strut.len() < 4 {
+            strut = get_cardinals(&xcb_conn, w, ewmh_conn.atoms._NET_WM_STRUT);
+        }
+        if strut.len() >= 4 {
+            left = left.max(strut[0]);
+            right = right.max(strut[1]);
+            top = top.max(strut[2]);
+            bottom = bottom.max(strut[3]);
+        }
+    }
+    if left + right >= full.width || top + bottom >= full.height {
+        return full;
+    }
+    Rect {
+        x: left as i32,
+        y: top as i32,
+        width: full.width - left - right,
+        height: full.height - top - bottom,
+    }
+}
+
+/// A monitor, as reported by RandR
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    /// the name of the output, e.g. `HDMI-1`
+    pub name: String,
+    pub rect: Rect,
+    pub primary: bool,
+}
+
+/// The name of the single monitor covering the screen, when RandR does not list the monitors
+pub const WHOLE_SCREEN: &str = "screen";
+
+/// The active monitors, from left to right (and from top to bottom); a single monitor
+/// covering the whole screen if the X server does not support RandR 1.5
+pub fn get_monitors() -> Vec<Monitor> {
+    let (xcb_conn, screen_id) =
+        xcb::Connection::connect_with_extensions(None, &[], &[xcb::Extension::RandR])
+            .expect("XCB connection failed in get_monitors");
+    let screen = xcb_conn
+        .get_setup()
+        .roots()
+        .nth(screen_id as usize)
+        .expect("no screen");
+    let whole_screen = vec![Monitor {
+        name: WHOLE_SCREEN.to_string(),
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels() as u32,
+            height: screen.height_in_pixels() as u32,
+        },
+        primary: true,
+    }];
+    if !xcb_conn
+        .active_extensions()
+        .any(|ext| ext == xcb::Extension::RandR)
+    {
+        return whole_screen;
+    }
+    let version_cookie = xcb_conn.send_request(&xcb::randr::QueryVersion {
+        major_version: 1,
+        minor_version: 5,
+    });
+    match xcb_conn.wait_for_reply(version_cookie) {
+        Ok(v) if (v.major_version(), v.minor_version()) >= (1, 5) => (),
+        _ => return whole_screen,
+    }
+    let cookie = xcb_conn.send_request(&xcb::randr::GetMonitors {
+        window: screen.root(),
+        get_active: true,
+    });
+    let reply = match xcb_conn.wait_for_reply(cookie) {
+        Ok(r) => r,
+        Err(_) => return whole_screen,
+    };
+    let mut monitors: Vec<Monitor> = reply
+        .monitors()
+        .filter(|m| m.width() > 0 && m.height() > 0)
+        .map(|m| {
+            let name_cookie = xcb_conn.send_request(&xcb::x::GetAtomName { atom: m.name() });
+            let name = match xcb_conn.wait_for_reply(name_cookie) {
+                Ok(repl) => repl.name().to_utf8().to_string(),
+                Err(_) => String::new(),
+            };
+            Monitor {
+                name,
+                rect: Rect {
+                    x: m.x() as i32,
+                    y: m.y() as i32,
+                    width: m.width() as u32,
+                    height: m.height() as u32,
+                },
+                primary: m.primary(),
+            }
+        })
+        .collect();
+    if monitors.is_empty() {
+        return whole_screen;
+    }
+    monitors.sort_by_key(|m| (m.rect.x, m.rect.y));
+    monitors
+}
+
+/// The position of the client window `win` in root coordinates, and its size
+pub fn get_window_rect(xcb_conn: &xcb::Connection, win: Window) -> Option<Rect> {
+    let geom_cookie = xcb_conn.send_request(&xcb::x::GetGeometry {
+        drawable: xcb::x::Drawable::Window(win),
+    });
+    let geom = xcb_conn.wait_for_reply(geom_cookie).ok()?;
+    let pos_cookie = xcb_conn.send_request(&xcb::x::TranslateCoordinates {
+        src_window: win,
+        dst_window: geom.root(),
+        src_x: 0,
+        src_y: 0,
+    });
+    let pos = xcb_conn.wait_for_reply(pos_cookie).ok()?;
+    Some(Rect {
+        x: pos.dst_x() as i32,
+        y: pos.dst_y() as i32,
+        width: geom.width() as u32,
+        height: geom.height() as u32,
+    })
+}
+
+/// The rectangle of `win` together with its decorations
+pub fn get_outer_rect(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    win: Window,
+) -> Option<Rect> {
+    let client = get_window_rect(xcb_conn, win)?;
+    let [left, right, top, bottom] = get_frame_extents(xcb_conn, ewmh_conn, win);
+    Some(Rect {
+        x: client.x - left as i32,
+        y: client.y - top as i32,
+        width: client.width + left + right,
+        height: client.height + top + bottom,
+    })
+}
+
+/// The monitor showing the largest part of `rect`
+pub fn monitor_at<'a>(monitors: &'a [Monitor], rect: &Rect) -> Option<&'a Monitor> {
+    monitors
+        .iter()
+        .map(|m| (m, rect.overlap(&m.rect)))
+        .filter(|(_, overlap)| *overlap > 0)
+        .max_by_key(|(_, overlap)| *overlap)
+        .map(|(m, _)| m)
+}
+
+/// The monitor with the pointer on it
+pub fn pointer_monitor<'a>(
+    xcb_conn: &xcb::Connection,
+    monitors: &'a [Monitor],
+) -> Option<&'a Monitor> {
+    let root = xcb_conn.get_setup().roots().next()?.root();
+    let pointer = xcb_conn
+        .wait_for_reply(xcb_conn.send_request(&xcb::x::QueryPointer { window: root }))
+        .ok()?;
+    let spot = Rect {
+        x: pointer.root_x() as i32,
+        y: pointer.root_y() as i32,
+        width: 1,
+        height: 1,
+    };
+    monitor_at(monitors, &spot)
+}
+
+/// The monitor where `win` is; if the window is off all monitors, the one with the pointer on it
+pub fn window_monitor<'a>(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    monitors: &'a [Monitor],
+    win: Window,
+) -> &'a Monitor {
+    get_outer_rect(xcb_conn, ewmh_conn, win)
+        .and_then(|r| monitor_at(monitors, &r))
+        .or_else(|| pointer_monitor(xcb_conn, monitors))
+        .or(monitors.first())
+        .expect("no monitors")
+}
+
+/// Finds a monitor by its number (counting from 1, left to right) or by the name of its output
+pub fn find_monitor<'a>(monitors: &'a [Monitor], spec: &str) -> Result<&'a Monitor, WintError> {
+    if let Ok(n) = spec.parse::<usize>() {
+        if n >= 1 && n <= monitors.len() {
+            return Ok(&monitors[n - 1]);
+        }
+    }
+    monitors
+        .iter()
+        .find(|m| m.name == spec)
+        .ok_or(WintError::UnknownMonitor(spec.to_string()))
+}
+
+thread_local! {
+    /// Whether the window manager supports `_NET_REQUEST_FRAME_EXTENTS`, once asked
+    static REQUEST_FRAME_EXTENTS: Cell<Option<bool>> = const { Cell::new(None) };
+    /// The extents of windows without `_NET_FRAME_EXTENTS`, so that they are only waited for once
+    static MISSING_FRAME_EXTENTS: RefCell<HashMap<u32, [u32; 4]>> = RefCell::new(HashMap::new());
+}
+
+/// `_NET_FRAME_EXTENTS` of a window: left, right, top, bottom. If the window manager has not set them yet
+/// for a window which is not mapped (e.g. a fresh window), asks for them with `_NET_REQUEST_FRAME_EXTENTS`
+/// and waits a little, if the window manager supports that. Windows without extents count as undecorated,
+/// and are not asked about again
+pub fn get_frame_extents(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    win: Window,
+) -> [u32; 4] {
+    let read = || get_cardinals(xcb_conn, win, ewmh_conn.atoms._NET_FRAME_EXTENTS);
+    let four = |extents: &[u32]| match extents[..] {
+        [left, right, top, bottom, ..] => Some([left, right, top, bottom]),
+        _ => None,
+    };
+    if let Some(extents) = four(&read()) {
+        return extents;
+    }
+    let id = win.resource_id();
+    if let Some(extents) = MISSING_FRAME_EXTENTS.with(|m| m.borrow().get(&id).copied()) {
+        return extents;
+    }
+    let supported = REQUEST_FRAME_EXTENTS.with(|s| {
+        *s.get().get_or_insert_with(|| {
+            wm_supports(ewmh_conn, ewmh_conn.atoms._NET_REQUEST_FRAME_EXTENTS)
+        })
+    });
+    let unmapped = xcb_conn
+        .wait_for_reply(xcb_conn.send_request(&xcb::x::GetWindowAttributes { window: win }))
+        .is_ok_and(|attrs| attrs.map_state() == xcb::x::MapState::Unmapped);
+    let mut extents = None;
+    if supported && unmapped {
+        let req = ewmh::proto::SendRequestFrameExtents::new(ewmh_conn, win);
+        if ewmh_conn.send_and_check_request(&req).is_ok() {
+            for _ in 0..10 {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                extents = four(&read());
+                if extents.is_some() {
+                    break;
+                }
+            }
+        }
+    }
+    let extents = extents.unwrap_or([0, 0, 0, 0]);
+    MISSING_FRAME_EXTENTS.with(|m| m.borrow_mut().insert(id, extents));
+    extents
+}
+
+/// Whether `WM_NORMAL_HINTS` asks for `StaticGravity`, i.e. the position of a configure request
+/// is that of the client window rather than of its frame
+pub fn has_static_gravity(xcb_conn: &xcb::Connection, win: Window) -> bool {
+    let hints = get_cardinals(xcb_conn, win, xcb::x::ATOM_WM_NORMAL_HINTS);
+    // PWinGravity is bit 9 of the flags, and the gravity is the 18th field
+    hints.len() >= 18 && hints[0] & (1 << 9) != 0 && hints[17] == xcb::x::Gravity::Static as u32
+}
+
+/// The sizes which a window accepts, according to its `WM_NORMAL_HINTS`
+pub fn get_size_hints(xcb_conn: &xcb::Connection, win: Window) -> SizeHints {
+    SizeHints::from_cardinals(&get_cardinals(xcb_conn, win, xcb::x::ATOM_WM_NORMAL_HINTS))
+}
+
+/// Sends a client message about `win` to the window manager (i.e. to the root window)
+pub fn send_wm_message(
+    xcb_conn: &xcb::Connection,
+    win: Window,
+    message_type: xcb::x::Atom,
+    data: [u32; 5],
+) -> xcb::ProtocolResult<()> {
+    let root = xcb_conn
+        .get_setup()
+        .roots()
+        .next()
+        .expect("no screen")
+        .root();
+    let event =
+        xcb::x::ClientMessageEvent::new(win, message_type, xcb::x::ClientMessageData::Data32(data));
+    let cookie = xcb_conn.send_request_checked(&xcb::x::SendEvent {
+        propagate: false,
+        destination: xcb::x::SendEventDest::Window(root),
+        event_mask: xcb::x::EventMask::SUBSTRUCTURE_NOTIFY
+            | xcb::x::EventMask::SUBSTRUCTURE_REDIRECT,
+        event: &event,
+    });
+    xcb_conn.check_request(cookie)
+}
+
+/// Whether the window manager lists `atom` in `_NET_SUPPORTED`
+pub fn wm_supports(ewmh_conn: &ewmh::Connection, atom: xcb::x::Atom) -> bool {
+    let supported_cookie = ewmh_conn.send_request(&ewmh::proto::GetSupported);
+    match ewmh_conn.wait_for_reply(supported_cookie) {
+        Ok(repl) => repl.atoms.contains(&atom),
+        Err(_) => false,
+    }
+}
+
+/// The `_NET_WM_STATE` atoms of a window
+pub fn get_wm_states(ewmh_conn: &ewmh::Connection, win: Window) -> Vec<xcb::x::Atom> {
+    let state_cookie = ewmh_conn.send_request(&ewmh::proto::GetWmState(win));
+    match ewmh_conn.wait_for_reply(state_cookie) {
+        Ok(repl) => repl.states,
+        Err(_) => vec![],
+    }
+}
+
+/// Takes a window out of the maximized and fullscreen states, which would make the window manager
+/// ignore (or undo) a new geometry
+pub fn unmaximize(xcb_conn: &xcb::Connection, ewmh_conn: &ewmh::Connection, win: Window) {
+    let states = get_wm_states(ewmh_conn, win);
+    let atoms = &ewmh_conn.atoms;
+    for pair in [
+        [
+            atoms._NET_WM_STATE_MAXIMIZED_VERT,
+            atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+        ],
+        [atoms._NET_WM_STATE_FULLSCREEN, xcb::x::ATOM_NONE],
+    ] {
+        if pair.iter().any(|state| states.contains(state)) {
+            // action 0 is _NET_WM_STATE_REMOVE, and source indication 2 means a pager or similar tool
+            let data = [0, pair[0].resource_id(), pair[1].resource_id(), 2, 0];
+            if let Err(e) = send_wm_message(xcb_conn, win, atoms._NET_WM_STATE, data) {
+                println!("Error changing the state of window {:?}: {:?}", win, e);
+            }
+        }
+    }
+}
+
+/// The geometry, desktop and states of a window, to be restored by `restore_window`
+pub fn save_window(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    win: Window,
+) -> Option<SavedWindow> {
+    let rect = get_outer_rect(xcb_conn, ewmh_conn, win)?;
+    let desktop = get_cardinals(xcb_conn, win, ewmh_conn.atoms._NET_WM_DESKTOP)
+        .first()
+        .copied()
+        .unwrap_or(0);
+    let states = get_wm_states(ewmh_conn, win);
+    let atoms = &ewmh_conn.atoms;
+    Some(SavedWindow {
+        id: win.resource_id(),
+        rect,
+        desktop,
+        maximized_vert: states.contains(&atoms._NET_WM_STATE_MAXIMIZED_VERT),
+        maximized_horz: states.contains(&atoms._NET_WM_STATE_MAXIMIZED_HORZ),
+        fullscreen: states.contains(&atoms._NET_WM_STATE_FULLSCREEN),
+        sticky: states.contains(&atoms._NET_WM_STATE_STICKY),
+        shaded: states.contains(&atoms._NET_WM_STATE_SHADED),
+        above: states.contains(&atoms._NET_WM_STATE_ABOVE),
+        below: states.contains(&atoms._NET_WM_STATE_BELOW),
+    })
+}
+
+/// Whether `_NET_WM_WINDOW_TYPE` makes `win` a panel or the desktop, which stay where they are
+pub fn is_dock_or_desktop(ewmh_conn: &ewmh::Connection, win: Window) -> bool {
+    let type_cookie = ewmh_conn.send_request(&ewmh::proto::GetWmWindowType(win));
+    match ewmh_conn.wait_for_reply(type_cookie) {
+        Ok(repl) => repl.window_types.iter().any(|t| {
+            *t == ewmh_conn.atoms._NET_WM_WINDOW_TYPE_DOCK
+                || *t == ewmh_conn.atoms._NET_WM_WINDOW_TYPE_DESKTOP
+        }),
+        Err(_) => false,
+    }
+}
+
+/// What `save_window` saves, together with the `WM_CLASS` and the title of the window; `None` for panels,
+/// the desktop, and windows of a class in the `blacklist`
+pub fn save_session_window(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    win: Window,
+    blacklist: &BlacklistedItems,
+) -> Option<SessionWindow> {
+    if is_dock_or_desktop(ewmh_conn, win) {
+        return None;
+    }
+    let icccm_conn = icccm::Connection::connect(xcb_conn);
+    let wmclass_cookie = icccm_conn.send_request(&icccm::proto::GetWmClass::new(win));
+    let (res_name, class) = match icccm_conn.wait_for_reply(wmclass_cookie) {
+        Ok(repl) => (repl.instance, repl.class),
+        Err(_) => (String::new(), String::new()),
+    };
+    if blacklist.contains(&class) {
+        return None;
+    }
+    let saved = save_window(xcb_conn, ewmh_conn, win)?;
+    let wmname_cookie = ewmh_conn.send_request(&ewmh::proto::GetWmName(win));
+    let title = match ewmh_conn.wait_for_reply(wmname_cookie) {
+        Ok(repl) => repl.name,
+        Err(_) => String::new(),
+    };
+    Some(SessionWindow {
+        class,
+        res_name,
+        title,
+        saved,
+    })
+}
+
+/// Puts a window back on its saved desktop, and into its saved states (the geometry is restored separately,
+/// like any tiling)
+pub fn restore_window(
+    xcb_conn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    win: Window,
+    saved: &SavedWindow,
+) -> xcb::ProtocolResult<()> {
+    let atoms = &ewmh_conn.atoms;
+    let desktop = get_cardinals(xcb_conn, win, atoms._NET_WM_DESKTOP);
+    if desktop.first() != Some(&saved.desktop) {
+        // source indication 2: a pager or similar tool
+        send_wm_message(
+            xcb_conn,
+            win,
+            atoms._NET_WM_DESKTOP,
+            [saved.desktop, 2, 0, 0, 0],
+        )?;
+    }
+    let current = get_wm_states(ewmh_conn, win);
+    for (on, state) in [
+        (saved.maximized_vert, atoms._NET_WM_STATE_MAXIMIZED_VERT),
+        (saved.maximized_horz, atoms._NET_WM_STATE_MAXIMIZED_HORZ),
+        (saved.fullscreen, atoms._NET_WM_STATE_FULLSCREEN),
+        (saved.sticky, atoms._NET_WM_STATE_STICKY),
+        (saved.shaded, atoms._NET_WM_STATE_SHADED),
+        (saved.above, atoms._NET_WM_STATE_ABOVE),
+        (saved.below, atoms._NET_WM_STATE_BELOW),
+    ] {
+        if on != current.contains(&state) {
+            // action 1 is _NET_WM_STATE_ADD, and 0 is _NET_WM_STATE_REMOVE
+            let data = [on as u32, state.resource_id(), 0, 2, 0];
+            send_wm_message(xcb_conn, win, atoms._NET_WM_STATE, data)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DesktopDirection {
+    Next,
+    Previous,
+    Up,
+    Down,
+}
+
+/// The grid of desktops, as described by `_NET_DESKTOP_LAYOUT`
+#[derive(Debug, Clone, Copy)]
+pub struct DesktopLayout {
+    pub vertical: bool,
+    pub columns: u32,
+    pub rows: u32,
+    pub starting_corner: u32,
+}
+
+impl DesktopLayout {
+    /// Without `_NET_DESKTOP_LAYOUT`, desktops form a single row
+    pub fn get(number_of_desktops: u32) -> DesktopLayout {
+        let (xcb_conn, screen_id) =
+            xcb::Connection::connect(None).expect("XCB connection failed in DesktopLayout::get");
+        let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+        let root = xcb_conn
+            .get_setup()
+            .roots()
+            .nth(screen_id as usize)
+            .expect("no screen")
+            .root();
+        let n = number_of_desktops.max(1);
+        let layout = get_cardinals(&xcb_conn, root, ewmh_conn.atoms._NET_DESKTOP_LAYOUT);
+        if layout.len() < 3 || (layout[1] == 0 && layout[2] == 0) {
+            return DesktopLayout {
+                vertical: false,
+                columns: n,
+                rows: 1,
+                starting_corner: 0,
+            };
+        }
+        let (columns, rows) = match (layout[1], layout[2]) {
+            (0, r) => (n.div_ceil(r), r),
+            (c, 0) => (c, n.div_ceil(c)),
+            (c, r) => (c, r),
+        };
+        DesktopLayout {
+            vertical: layout[0] == 1,
+            columns,
+            rows,
+            starting_corner: *layout.get(3).unwrap_or(&0),
+        }
+    }
+
+    fn position(&self, desktop: u32) -> (u32, u32) {
+        let (row, col) = if self.vertical {
+            (desktop % self.rows, desktop / self.rows)
+        } else {
+            (desktop / self.columns, desktop % self.columns)
+        };
+        match self.starting_corner {
+            1 => (row, self.columns - 1 - col),
+            2 => (self.rows - 1 - row, self.columns - 1 - col),
+            3 => (self.rows - 1 - row, col),
+            _ => (row, col),
+        }
+    }
+
+    fn desktop_at(&self, row: u32, col: u32) -> u32 {
+        let (row, col) = match self.starting_corner {
+            1 => (row, self.columns - 1 - col),
+            2 => (self.rows - 1 - row, self.columns - 1 - col),
+            3 => (self.rows - 1 - row, col),
+            _ => (row, col),
+        };
+        if self.vertical {
+            col * self.rows + row
+        } else {
+            row * self.columns + col
+        }
+    }
+
+    /// The desktop reached from `current` by moving in `direction`, or `None` at the edge
+    pub fn relative_desktop(
+        &self,
+        current: u32,
+        number_of_desktops: u32,
+        direction: DesktopDirection,
+        wrap: bool,
+    ) -> Option<u32> {
+        let n = number_of_desktops;
+        if n == 0 || current >= n {
+            return None;
+        }
+        match direction {
+            DesktopDirection::Next if current + 1 < n => Some(current + 1),
+            DesktopDirection::Next if wrap => Some(0),
+            DesktopDirection::Previous if current > 0 => Some(current - 1),
+            DesktopDirection::Previous if wrap => Some(n - 1),
+            DesktopDirection::Next | DesktopDirection::Previous => None,
+            DesktopDirection::Up | DesktopDirection::Down => {
+                if current >= self.columns * self.rows {
+                    return None;
+                }
+                let (mut row, col) = self.position(current);
+                // an incomplete last row may have holes, so keep moving until we land on a desktop
+                for _ in 0..self.rows {
+                    row = match direction {
+                        DesktopDirection::Up if row > 0 => row - 1,
+                        DesktopDirection::Down if row + 1 < self.rows => row + 1,
+                        DesktopDirection::Up if wrap => self.rows - 1,
+                        DesktopDirection::Down if wrap => 0,
+                        _ => return None,
+                    };
+                    let target = self.desktop_at(row, col);
+                    if target < n && target != current {
+                        return Some(target);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Picks the smallest `_NET_WM_ICON` image which is at least `size` pixels wide (or else the largest one),
+/// and converts it from ARGB to RGBA; returns the width, the height and the pixels
+pub fn choose_wm_icon(data: &[u32], size: u32) -> Option<(u32, u32, Vec<u8>)> {
+    let mut best: Option<(u32, u32, usize)> = None;
+    let mut i = 0;
+    while i + 2 <= data.len() {
+        let (w, h) = (data[i], data[i + 1]);
+        let len = (w as usize) * (h as usize);
+        if len == 0 || i + 2 + len > data.len() {
+            break;
+        }
+        let better = match best {
+            None => true,
+            Some((bw, _, _)) if bw >= size => w >= size && w < bw,
+            Some((bw, _, _)) => w > bw,
+        };
+        if better {
+            best = Some((w, h, i + 2));
+        }
+        i += 2 + len;
+    }
+    best.map(|(w, h, offset)| {
+        let rgba = data[offset..offset + (w as usize) * (h as usize)]
+            .iter()
+            .flat_map(|argb| {
+                let [b, g, r, a] = argb.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect();
+        (w, h, rgba)
+    })
+}
+
+fn themed_icon(theme: &gtk::IconTheme, class: &str, size: i32) -> Option<Pixbuf> {
+    [class.to_string(), class.to_lowercase()]
+        .iter()
+        .find(|name| theme.has_icon(name))
+        .and_then(|name| {
+            theme
+                .load_icon(name, size, gtk::IconLookupFlags::FORCE_SIZE)
+                .ok()
+                .flatten()
+        })
+}
+
+/// Icons from `_NET_WM_ICON`, falling back to the icon theme entry named after `res_class`
+fn make_icons(wins: &[&WinInfo], size: i32) -> HashMap<Window, gtk::Image> {
+    let (xcb_conn, _screen_id) =
+        xcb::Connection::connect(None).expect("XCB connection failed in make_icons");
+    let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+    let theme = gtk::IconTheme::default();
+    wins.iter()
+        .filter_map(|win| {
+            let data = get_cardinals(&xcb_conn, win.0, ewmh_conn.atoms._NET_WM_ICON);
+            let from_property = choose_wm_icon(&data, size as u32).and_then(|(w, h, rgba)| {
+                let pixbuf = Pixbuf::from_bytes(
+                    &glib::Bytes::from_owned(rgba),
+                    Colorspace::Rgb,
+                    true,
+                    8,
+                    w as i32,
+                    h as i32,
+                    4 * w as i32,
+                );
+                let (sw, sh) = if w >= h {
+                    (size, (size * h as i32 / w as i32).max(1))
+                } else {
+                    ((size * w as i32 / h as i32).max(1), size)
+                };
+                pixbuf.scale_simple(sw, sh, InterpType::Bilinear)
+            });
+            from_property
+                .or_else(|| theme.as_ref().and_then(|t| themed_icon(t, &win.3, size)))
+                .map(|pixbuf| (win.0, gtk::Image::from_pixbuf(Some(&pixbuf))))
+        })
+        .collect()
+}
+
+/// The child of the root window containing `win`, i.e. the window manager's frame (or `win` itself)
+pub fn toplevel_window(xcb_conn: &xcb::Connection, win: Window) -> Window {
+    let mut current = win;
+    loop {
+        let cookie = xcb_conn.send_request(&xcb::x::QueryTree { window: current });
+        match xcb_conn.wait_for_reply(cookie) {
+            Ok(tree) if tree.parent() != tree.root() && tree.parent() != Window::none() => {
+                current = tree.parent();
+            }
+            _ => return current,
+        }
+    }
+}
+
+/// Whether window contents can be taken from Composite pixmaps, i.e. a compositing manager is running
+pub fn compositor_running(xcb_conn: &xcb::Connection, screen_id: i32) -> bool {
+    if !xcb_conn
+        .active_extensions()
+        .any(|ext| ext == xcb::Extension::Composite)
+    {
+        return false;
+    }
+    let version_cookie = xcb_conn.send_request(&xcb::composite::QueryVersion {
+        client_major_version: 0,
+        client_minor_version: 4,
+    });
+    if xcb_conn.wait_for_reply(version_cookie).is_err() {
+        return false;
+    }
+    let name = format!("_NET_WM_CM_S{}", screen_id);
+    let atom_cookie = xcb_conn.send_request(&xcb::x::InternAtom {
+        only_if_exists: true,
+        name: name.as_bytes(),
+    });
+    let atom = match xcb_conn.wait_for_reply(atom_cookie) {
+        Ok(repl) if repl.atom() != xcb::x::ATOM_NONE => repl.atom(),
+        _ => return false,
+    };
+    let owner_cookie = xcb_conn.send_request(&xcb::x::GetSelectionOwner { selection: atom });
+    match xcb_conn.wait_for_reply(owner_cookie) {
+        Ok(repl) => repl.owner() != Window::none(),
+        Err(_) => false,
+    }
+}
+
+/// Captures the contents of a viewable window as RGBA pixels; returns the width, the height and the pixels.
+/// With `composite`, the pixels are taken from the offscreen pixmap of the window's frame,
+/// so that they are correct even if the window is covered by other windows.
+/// Gives `None` for unmapped windows (e.g. windows on other desktops).
+pub fn capture_window(
+    xcb_conn: &xcb::Connection,
+    win: Window,
+    composite: bool,
+) -> Option<(u32, u32, Vec<u8>)> {
+    let attrs_cookie = xcb_conn.send_request(&xcb::x::GetWindowAttributes { window: win });
+    let attrs = xcb_conn.wait_for_reply(attrs_cookie).ok()?;
+    if attrs.map_state() != xcb::x::MapState::Viewable {
+        return None;
+    }
+    let geom_cookie = xcb_conn.send_request(&xcb::x::GetGeometry {
+        drawable: xcb::x::Drawable::Window(win),
+    });
+    let geom = xcb_conn.wait_for_reply(geom_cookie).ok()?;
+    let (width, height) = (geom.width(), geom.height());
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let pixmap = if composite {
+        name_frame_pixmap(xcb_conn, win)
+    } else {
+        None
+    };
+    let (drawable, x, y) = match pixmap {
+        Some((p, x, y)) => (xcb::x::Drawable::Pixmap(p), x, y),
+        None => (xcb::x::Drawable::Window(win), 0, 0),
+    };
+    let image_cookie = xcb_conn.send_request(&xcb::x::GetImage {
+        format: xcb::x::ImageFormat::ZPixmap,
+        drawable,
+        x,
+        y,
+        width,
+        height,
+        plane_mask: u32::MAX,
+    });
+    let image = xcb_conn.wait_for_reply(image_cookie);
+    if let Some((p, _, _)) = pixmap {
+        xcb_conn.send_request(&xcb::x::FreePixmap { pixmap: p });
+    }
+    let image = image.ok()?;
+    let npixels = (width as usize) * (height as usize);
+    // depth 24 and 32 images have 4 bytes per pixel, in the order B, G, R, X
+    if (image.depth() != 24 && image.depth() != 32) || image.data().len() < 4 * npixels {
+        return None;
+    }
+    let rgba = image
+        .data()
+        .chunks_exact(4)
+        .take(npixels)
+        .flat_map(|bgrx| [bgrx[2], bgrx[1], bgrx[0], 255])
+        .collect();
+    Some((width as u32, height as u32, rgba))
+}
+
+/// Names the Composite pixmap of the frame containing `win`; returns it with the offset of `win` in it
+fn name_frame_pixmap(
+    xcb_conn: &xcb::Connection,
+    win: Window,
+) -> Option<(xcb::x::Pixmap, i16, i16)> {
+    let frame = toplevel_window(xcb_conn, win);
+    let frame_geom_cookie = xcb_conn.send_request(&xcb::x::GetGeometry {
+        drawable: xcb::x::Drawable::Window(frame),
+    });
+    let frame_geom = xcb_conn.wait_for_reply(frame_geom_cookie).ok()?;
+    let offset_cookie = xcb_conn.send_request(&xcb::x::TranslateCoordinates {
+        src_window: win,
+        dst_window: frame,
+        src_x: 0,
+        src_y: 0,
+    });
+    let offset = xcb_conn.wait_for_reply(offset_cookie).ok()?;
+    let pixmap: xcb::x::Pixmap = xcb_conn.generate_id();
+    let cookie = xcb_conn.send_request_checked(&xcb::composite::NameWindowPixmap {
+        window: frame,
+        pixmap,
+    });
+    match xcb_conn.check_request(cookie) {
+        // the pixmap includes the border of the frame:
+        Ok(_) => Some((
+            pixmap,
+            offset.dst_x() + frame_geom.border_width() as i16,
+            offset.dst_y() + frame_geom.border_width() as i16,
+        )),
+        Err(_) => None,
+    }
+}
+
+/// Thumbnails of the windows which are currently viewable, scaled to fit into `size` pixels
+fn make_thumbnails(wins: &[&WinInfo], size: i32) -> HashMap<Window, gtk::Image> {
+    let (xcb_conn, screen_id) =
+        xcb::Connection::connect_with_extensions(None, &[], &[xcb::Extension::Composite])
+            .expect("XCB connection failed in make_thumbnails");
+    let composite = compositor_running(&xcb_conn, screen_id);
+    wins.iter()
+        .filter_map(|win| {
+            let (w, h, rgba) = capture_window(&xcb_conn, win.0, composite)?;
+            let pixbuf = Pixbuf::from_bytes(
+                &glib::Bytes::from_owned(rgba),
+                Colorspace::Rgb,
+                true,
+                8,
+                w as i32,
+                h as i32,
+                4 * w as i32,
+            );
+            let (sw, sh) = if w >= h {
+                (size, (size * h as i32 / w as i32).max(1))
+            } else {
+                ((size * w as i32 / h as i32).max(1), size)
+            };
+            pixbuf
+                .scale_simple(sw, sh, InterpType::Bilinear)
+                .map(|scaled| (win.0, gtk::Image::from_pixbuf(Some(&scaled))))
+        })
+        .collect()
+}
+
+fn make_desktop_header(desktop: u32, desktop_names: &Rc<Vec<String>>, current: bool) -> gtk::Label {
+    let (text, class) = if desktop == STICKY_DESKTOP {
+        (
+            String::from("all desktops"),
+            String::from("desktop_header_sticky"),
+        )
+    } else {
+        match desktop_names.get(desktop as usize) {
+            Some(name) if !name.is_empty() => (
+                format!("{}: {}", desktop + 1, name),
+                format!("desktop_header_{}", desktop + 1),
+            ),
+            _ => (
+                format!("{}", desktop + 1),
+                format!("desktop_header_{}", desktop + 1),
+            ),
+        }
+    };
+    let header = gtk::Label::new(Some(&text));
+    header.set_xalign(0.0);
+    header.style_context().add_class("desktop_header");
+    header.style_context().add_class(&class);
+    if current {
+        header.style_context().add_class("desktop_header_current");
+    }
+    header
+}
+
+fn make_row(
+    j: u8,
+    (num, _win_desktop, name, class, urgent): &WinInfo,
+    active: &Window,
+    style: &RowStyle,
+    icon: Option<&gtk::Image>,
+    thumbnail: Option<&gtk::Image>,
+) -> gtk::Box {
+    let space_between_buttons = style.space_between_buttons;
+    let class_sanitized = class.replace(".", "_");
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, space_between_buttons);
+    let lbtn = gtk::Button::new();
+    let llbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
+    if num == active {
+        lbtn.style_context().add_class("wmjump_lbtn_current");
+    } else {
+        lbtn.style_context()
+            .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+        lbtn.style_context().add_class("wmjump_lbtn");
+    }
+    lbtn.add(&llbl);
+    let rbtn = gtk::Button::new();
+    let rlbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
+    if num == active {
+        rbtn.style_context().add_class("wmjump_rbtn_current");
+    } else {
+        rbtn.style_context()
+            .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+        rbtn.style_context().add_class("wmjump_rbtn");
+    }
+    rbtn.add(&rlbl);
+    let btn = gtk::Button::new();
+    let truncated = name.clone();
+    let lbl = gtk::Label::new(Some(&abbreviate(truncated, style.maxlen)));
+    btn.style_context()
+        .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+    btn.style_context().add_class("wmjump_button");
+    if icon.is_none() && thumbnail.is_none() {
+        btn.add(&lbl);
+    } else {
+        let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, space_between_buttons);
+        if let Some(image) = icon {
+            image.style_context().add_class("wmjump_icon");
+            btn_box.add(image);
+        }
+        if let Some(image) = thumbnail {
+            image.style_context().add_class("wmjump_thumbnail");
+            btn_box.add(image);
+        }
+        btn_box.add(&lbl);
+        btn.add(&btn_box);
+    }
+    if *urgent {
+        lbtn.style_context().add_class("wmjump_urgent");
+        btn.style_context().add_class("wmjump_urgent");
+        rbtn.style_context().add_class("wmjump_urgent");
+    }
+    hbox.add(&lbtn);
+    hbox.add(&btn);
+    hbox.add(&rbtn);
+    hbox
+}
+
+/// The windows which are not blacklisted
+fn shown_windows<'a>(wins: &'a [WinInfo], blacklist: &BlacklistedItems) -> Vec<&'a WinInfo> {
+    wins.iter()
+        .filter(|win| !blacklist.contains(&win.3))
+        .collect()
+}
+
+/// The windows on desktop `d`, in the order of their charhints
+fn windows_on_desktop<'a>(shown: &[&'a WinInfo], d: u32, urgent_first: bool) -> Vec<&'a WinInfo> {
+    let mut on_desktop: Vec<&WinInfo> = shown.iter().filter(|win| win.1 == d).copied().collect();
+    if urgent_first {
+        on_desktop.sort_by_key(|win| !win.4);
+    }
+    on_desktop
+}
+
+/// The windows on `desktop` in the order of their charhints, as listed by `make_vbox`
+pub fn hinted_windows<'a>(
+    wins: &'a [WinInfo],
+    desktop: u32,
+    blacklist: &BlacklistedItems,
+    style: &RowStyle,
+) -> Vec<&'a WinInfo> {
+    windows_on_desktop(&shown_windows(wins, blacklist), desktop, style.urgent_first)
+}
+
+/// The list of windows with their charhints: on `desktop` only, or else on all desktops, with the header of
+/// `current_desktop` (as given by `_NET_CURRENT_DESKTOP`) marked
 pub fn make_vbox(
-    wins: &Rc<Vec<(Window, u32, String, String)>>,
+    wins: &Rc<Vec<WinInfo>>,
     desktop: Option<u32>,
-    space_between_buttons: i32,
-    maxlen: usize,
+    current_desktop: u32,
+    desktop_names: &Rc<Vec<String>>,
     blacklist: &Rc<BlacklistedItems>,
     active: &Window,
+    style: &RowStyle,
 ) -> (gtk::Box, HashMap<u8, Window>) {
-    let vbox = gtk::Box::new(gtk::Orientation::Vertical, space_between_buttons);
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, style.space_between_buttons);
     vbox.style_context().add_class("main_vbox");
     let mut charhints: HashMap<u8, Window> = HashMap::new();
     let mut j = 0 as u8;
@@ -193,60 +1379,58 @@ pub fn make_vbox(
         Some(d) => println!("only showing windows on desktop {}", d),
         None => println!("showing windows on all desktops"),
     }
-    for (num, win_desktop, name, class) in (*wins)
+    let shown = shown_windows(wins, blacklist);
+    // In the all-desktops view, empty desktops get a header too:
+    let desktops: Vec<u32> = match desktop {
+        Some(d) => vec![d],
+        None => {
+            let mut ds: Vec<u32> = (0..desktop_names.len() as u32).collect();
+            for win in shown.iter() {
+                if win.1 != STICKY_DESKTOP && !ds.contains(&win.1) {
+                    ds.push(win.1);
+                }
+            }
+            if shown.iter().any(|win| win.1 == STICKY_DESKTOP) {
+                ds.push(STICKY_DESKTOP);
+            }
+            ds
+        }
+    };
+    // icons and thumbnails only for the rows which are listed
+    let listed: Vec<&WinInfo> = shown
         .iter()
-        .filter(|win| match desktop {
-            Some(d) => d == win.1,
-            None => true,
-        })
-        .filter(|win| {
-            !(*blacklist)
-                .item
-                .iter()
-                .map(|i| &i.class)
-                .collect::<Vec<&String>>()
-                .contains(&&win.3)
-        })
-    {
-        let class_sanitized = class.replace(".", "_");
-        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, space_between_buttons);
-        let lbtn = gtk::Button::new();
-        let llbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
-        if num == active {
-            lbtn.style_context().add_class("wmjump_lbtn_current");
-        } else {
-            lbtn.style_context()
-                .add_class(&["wbtn_", &class_sanitized].concat()[..]);
-            lbtn.style_context().add_class("wmjump_lbtn");
+        .filter(|win| desktops.contains(&win.1))
+        .copied()
+        .collect();
+    let icons = match style.icon_size {
+        Some(size) => make_icons(&listed, size),
+        None => HashMap::new(),
+    };
+    let thumbnails = match style.thumbnail_size {
+        Some(size) => make_thumbnails(&listed, size),
+        None => HashMap::new(),
+    };
+    for d in desktops {
+        vbox.add(&make_desktop_header(d, desktop_names, d == current_desktop));
+        let on_desktop = windows_on_desktop(&shown, d, style.urgent_first);
+        if on_desktop.is_empty() {
+            let empty = gtk::Label::new(Some("(no windows)"));
+            empty.set_xalign(0.0);
+            empty.style_context().add_class("desktop_empty");
+            vbox.add(&empty);
         }
-        lbtn.add(&llbl);
-        let rbtn = gtk::Button::new();
-        let rlbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
-        if num == active {
-            rbtn.style_context().add_class("wmjump_rbtn_current");
-        } else {
-            rbtn.style_context()
-                .add_class(&["wbtn_", &class_sanitized].concat()[..]);
-            rbtn.style_context().add_class("wmjump_rbtn");
+        for win in on_desktop {
+            vbox.add(&make_row(
+                j,
+                win,
+                active,
+                style,
+                icons.get(&win.0),
+                thumbnails.get(&win.0),
+            ));
+            charhints.insert(j, win.0);
+            j += 1;
         }
-        rbtn.add(&rlbl);
-        let btn = gtk::Button::new();
-        let truncated = name.clone();
-        let lbl = gtk::Label::new(Some(&format!(
-            "{}: {}",
-            win_desktop + 1,
-            abbreviate(truncated, maxlen)
-        )));
-        btn.style_context()
-            .add_class(&["wbtn_", &class_sanitized].concat()[..]);
-        btn.style_context().add_class("wmjump_button");
-        btn.add(&lbl);
-        hbox.add(&lbtn);
-        hbox.add(&btn);
-        hbox.add(&rbtn);
-        vbox.add(&hbox);
-        charhints.insert(j, *num);
-        j += 1;
     }
     return (vbox, charhints);
 }
@@ -258,6 +1442,20 @@ pub fn get_config_dir() -> PathBuf {
     }
     p
 }
+/// The file of the session `name` saved by `wint --save-session`, in the `sessions` directory of the configuration
+pub fn session_path(name: &str) -> Result<PathBuf, WintError> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(WintError::BadCommand(format!(
+            "{:?} can not be the name of a session",
+            name
+        )));
+    }
+    let dir = Path::join(&get_config_dir(), "sessions");
+    if !dir.exists() {
+        std::fs::create_dir(&dir)?;
+    }
+    Ok(Path::join(&dir, name))
+}
 pub fn get_conf() -> Result<Config, WintError> {
     let config_dir = get_config_dir();
     let config_file_path = Path::join(&config_dir, "config.xml");
@@ -309,3 +1507,147 @@ pub fn go_to_window(win: Window, ewmh_conn: &ewmh::Connection) {
 
     println!("-- going to window {:?}\n   ...", win);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(columns: u32, rows: u32, vertical: bool, starting_corner: u32) -> DesktopLayout {
+        DesktopLayout {
+            vertical,
+            columns,
+            rows,
+            starting_corner,
+        }
+    }
+
+    #[test]
+    fn places_desktops_from_each_starting_corner() {
+        // desktop 0 is in the top left, top right, bottom right and bottom left corner of a 3x2 grid
+        let corners = [(0, 0), (0, 2), (1, 2), (1, 0)];
+        for (corner, first) in corners.iter().enumerate() {
+            let l = layout(3, 2, false, corner as u32);
+            assert_eq!(l.position(0), *first, "corner {}", corner);
+            for desktop in 0..6 {
+                let (row, col) = l.position(desktop);
+                assert_eq!(l.desktop_at(row, col), desktop, "corner {}", corner);
+            }
+        }
+        assert_eq!(layout(3, 2, false, 0).position(4), (1, 1));
+        assert_eq!(layout(3, 2, false, 2).position(4), (0, 1));
+    }
+
+    #[test]
+    fn places_desktops_in_columns_when_vertical() {
+        let l = layout(3, 2, true, 0);
+        assert_eq!(l.position(1), (1, 0));
+        assert_eq!(l.position(2), (0, 1));
+        assert_eq!(l.desktop_at(1, 2), 5);
+        assert_eq!(
+            l.relative_desktop(0, 6, DesktopDirection::Down, false),
+            Some(1)
+        );
+        assert_eq!(
+            l.relative_desktop(0, 6, DesktopDirection::Next, false),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn moves_up_and_down_between_rows() {
+        let l = layout(3, 2, false, 0);
+        assert_eq!(
+            l.relative_desktop(1, 6, DesktopDirection::Down, false),
+            Some(4)
+        );
+        assert_eq!(
+            l.relative_desktop(4, 6, DesktopDirection::Up, false),
+            Some(1)
+        );
+        assert_eq!(l.relative_desktop(1, 6, DesktopDirection::Up, false), None);
+        assert_eq!(
+            l.relative_desktop(1, 6, DesktopDirection::Up, true),
+            Some(4)
+        );
+        // with desktop 0 at the bottom left, up is the next row
+        let l = layout(3, 2, false, 3);
+        assert_eq!(
+            l.relative_desktop(1, 6, DesktopDirection::Up, false),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn skips_the_holes_of_an_incomplete_last_row() {
+        // 0 1 2
+        // 3 4 5
+        // 6
+        let l = layout(3, 3, false, 0);
+        assert_eq!(
+            l.relative_desktop(5, 7, DesktopDirection::Down, false),
+            None
+        );
+        assert_eq!(
+            l.relative_desktop(5, 7, DesktopDirection::Down, true),
+            Some(2)
+        );
+        assert_eq!(
+            l.relative_desktop(2, 7, DesktopDirection::Up, true),
+            Some(5)
+        );
+        assert_eq!(
+            l.relative_desktop(0, 7, DesktopDirection::Up, true),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn wraps_around_the_list() {
+        let l = layout(5, 1, false, 0);
+        assert_eq!(
+            l.relative_desktop(4, 5, DesktopDirection::Next, false),
+            None
+        );
+        assert_eq!(
+            l.relative_desktop(4, 5, DesktopDirection::Next, true),
+            Some(0)
+        );
+        assert_eq!(
+            l.relative_desktop(0, 5, DesktopDirection::Previous, true),
+            Some(4)
+        );
+        assert_eq!(l.relative_desktop(0, 5, DesktopDirection::Down, true), None);
+        assert_eq!(l.relative_desktop(7, 5, DesktopDirection::Next, true), None);
+    }
+
+    #[test]
+    fn chooses_the_smallest_icon_at_least_as_large() {
+        let icon = |size: u32, argb: u32| -> Vec<u32> {
+            let mut data = vec![size, size];
+            data.resize(2 + (size * size) as usize, argb);
+            data
+        };
+        let data = [icon(16, 1), icon(48, 2), icon(32, 3), icon(64, 4)].concat();
+        let (w, h, rgba) = choose_wm_icon(&data, 24).unwrap();
+        assert_eq!((w, h, rgba.len()), (32, 32, 32 * 32 * 4));
+        assert_eq!(choose_wm_icon(&data, 48).unwrap().0, 48);
+        // none is large enough: the largest one
+        assert_eq!(choose_wm_icon(&data, 128).unwrap().0, 64);
+        // a truncated image is left out
+        assert_eq!(
+            choose_wm_icon(&[icon(16, 1), vec![32, 32, 0]].concat(), 24)
+                .unwrap()
+                .0,
+            16
+        );
+        assert_eq!(choose_wm_icon(&[], 24), None);
+        assert_eq!(choose_wm_icon(&[16], 24), None);
+    }
+
+    #[test]
+    fn converts_argb_to_rgba() {
+        let (w, h, rgba) = choose_wm_icon(&[2, 1, 0x80ff4020, 0xff000000], 2).unwrap();
+        assert_eq!((w, h), (2, 1));
+        assert_eq!(rgba, vec![0xff, 0x40, 0x20, 0x80, 0, 0, 0, 0xff]);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::history::SavedWindow;
+use std::path::Path;
+
+/// A window recorded by `wint --save-session`, with what identifies it after a restart
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionWindow {
+    /// the `res_class` of `WM_CLASS`
+    pub class: String,
+    /// the `res_name` of `WM_CLASS`
+    pub res_name: String,
+    pub title: String,
+    pub saved: SavedWindow,
+}
+
+/// Tabs separate the fields of the session file, so they can not be part of them
+fn clean(field: &str) -> String {
+    field.replace(['\t', '\n'], " ")
+}
+
+impl SessionWindow {
+    /// One line of the session file: the word of the history file (see `SavedWindow`), the class,
+    /// the `res_name` and the title, separated by tabs
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.saved.to_word(),
+            clean(&self.class),
+            clean(&self.res_name),
+            clean(&self.title)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<SessionWindow> {
+        let mut fields = line.splitn(4, '\t');
+        let saved = SavedWindow::from_word(fields.next()?)?;
+        Some(SessionWindow {
+            class: fields.next()?.to_string(),
+            res_name: fields.next()?.to_string(),
+            title: fields.next().unwrap_or("").to_string(),
+            saved,
+        })
+    }
+}
+
+/// All the client windows, as they were when the session was saved
+#[derive(Debug, Default)]
+pub struct Session {
+    pub windows: Vec<SessionWindow>,
+}
+
+impl Session {
+    /// Reads a session file; unreadable lines are skipped
+    pub fn read(path: &Path) -> std::io::Result<Session> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Session {
+            windows: text.lines().filter_map(SessionWindow::from_line).collect(),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let lines: Vec<String> = self.windows.iter().map(|w| w.to_line()).collect();
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Pairs the `windows`, given as (window, id, class, `res_name`, title), with the saved ones. A window only
+    /// matches a saved window of the same class; among those, the same id (the window was never closed) comes first,
+    /// then the same `res_name`, then the most similar title. Each window is used at most once
+    pub fn assign<T: Copy + PartialEq>(
+        &self,
+        windows: &[(T, u32, &str, &str, &str)],
+    ) -> Vec<(T, &SessionWindow)> {
+        let mut candidates: Vec<(f64, usize, usize)> = vec![];
+        for (i, saved) in self.windows.iter().enumerate() {
+            for (j, (_, id, class, res_name, title)) in windows.iter().enumerate() {
+                if *class != saved.class {
+                    continue;
+                }
+                let mut score = title_similarity(title, &saved.title);
+                if *res_name == saved.res_name {
+                    score += 1.0;
+                }
+                if *id == saved.saved.id {
+                    score += 2.0;
+                }
+                candidates.push((score, i, j));
+            }
+        }
+        // the best pairs first; on a tie, in the order of the session file and of the windows
+        candidates.sort_by(|a, b| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then((a.1, a.2).cmp(&(b.1, b.2)))
+        });
+        let mut saved_used = vec![false; self.windows.len()];
+        let mut windows_used = vec![false; windows.len()];
+        let mut assigned = vec![];
+        for (_, i, j) in candidates {
+            if saved_used[i] || windows_used[j] {
+                continue;
+            }
+            saved_used[i] = true;
+            windows_used[j] = true;
+            assigned.push((windows[j].0, &self.windows[i]));
+        }
+        assigned
+    }
+}
+
+/// How much two titles look alike, from 0 to 1: the Sørensen–Dice coefficient of their pairs of adjacent characters
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.to_lowercase().chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let (a, mut b) = (bigrams(a), bigrams(b));
+    let total = a.len() + b.len();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut common = 0;
+    for pair in a.iter() {
+        if let Some(k) = b.iter().position(|p| p == pair) {
+            b.swap_remove(k);
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilings::Rect;
+
+    fn window(id: u32, class: &str, res_name: &str, title: &str) -> SessionWindow {
+        SessionWindow {
+            class: class.to_string(),
+            res_name: res_name.to_string(),
+            title: title.to_string(),
+            saved: SavedWindow {
+                id,
+                rect: Rect::new(0, 0, 100, 100),
+                desktop: 0,
+                maximized_vert: false,
+                maximized_horz: false,
+                fullscreen: false,
+                sticky: false,
+                shaded: false,
+                above: false,
+                below: false,
+            },
+        }
+    }
+
+    fn session(windows: Vec<SessionWindow>) -> Session {
+        Session { windows }
+    }
+
+    /// The titles of the saved windows given to the windows 'a', 'b', ...
+    fn pairs(session: &Session, windows: &[(char, u32, &str, &str, &str)]) -> Vec<(char, String)> {
+        let mut pairs: Vec<(char, String)> = session
+            .assign(windows)
+            .into_iter()
+            .map(|(w, saved)| (w, saved.title.clone()))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        let w = window(7, "Emacs", "emacs", "notes.org\t- GNU Emacs");
+        let line = w.to_line();
+        assert_eq!(line.matches('\t').count(), 3);
+        let read = SessionWindow::from_line(&line).unwrap();
+        assert_eq!(read.title, "notes.org - GNU Emacs");
+        assert_eq!(read, window(7, "Emacs", "emacs", "notes.org - GNU Emacs"));
+        assert!(SessionWindow::from_line("garbage\tEmacs\temacs\ttitle").is_none());
+    }
+
+    #[test]
+    fn the_same_id_wins() {
+        let s = session(vec![
+            window(1, "XTerm", "xterm", "vim"),
+            window(2, "XTerm", "xterm", "htop"),
+        ]);
+        let found = pairs(
+            &s,
+            &[
+                ('a', 2, "XTerm", "xterm", "vim"),
+                ('b', 9, "XTerm", "xterm", "htop"),
+            ],
+        );
+        assert_eq!(
+            found,
+            vec![('a', "htop".to_string()), ('b', "vim".to_string())]
+        );
+    }
+
+    #[test]
+    fn then_the_res_name_then_the_closest_title() {
+        let s = session(vec![
+            window(1, "Firefox", "Navigator", "Mail - Firefox"),
+            window(2, "Firefox", "Toolkit", "Mail"),
+            window(3, "Firefox", "Navigator", "News - Firefox"),
+        ]);
+        let found = pairs(
+            &s,
+            &[
+                ('a', 10, "Firefox", "Navigator", "Mail"),
+                ('b', 11, "Firefox", "Navigator", "News today - Firefox"),
+            ],
+        );
+        assert_eq!(
+            found,
+            vec![
+                ('a', "Mail - Firefox".to_string()),
+                ('b', "News - Firefox".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_each_window_once_and_only_with_its_class() {
+        let s = session(vec![
+            window(1, "XTerm", "xterm", "a"),
+            window(2, "XTerm", "xterm", "b"),
+            window(3, "Emacs", "emacs", "c"),
+        ]);
+        let found = pairs(
+            &s,
+            &[
+                ('a', 1, "XTerm", "xterm", "a"),
+                ('b', 4, "Gimp", "gimp", "c"),
+            ],
+        );
+        assert_eq!(found, vec![('a', "a".to_string())]);
+        let assigned = s.assign(&[('a', 1, "XTerm", "xterm", "a")]);
+        assert_eq!(assigned.len(), 1);
+    }
+
+    #[test]
+    fn compares_titles() {
+        assert_eq!(title_similarity("vim", "vim"), 1.0);
+        assert_eq!(title_similarity("", "vim"), 0.0);
+        assert_eq!(title_similarity("Mail - Firefox", "mail - firefox"), 1.0);
+        assert!(title_similarity("News - Firefox", "News today - Firefox") > 0.5);
+        assert_eq!(title_similarity("abc", "xyz"), 0.0);
+    }
+}
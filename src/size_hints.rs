@@ -0,0 +1,317 @@
+use crate::tilings::Rect;
+
+// flags of WM_NORMAL_HINTS
+const P_MIN_SIZE: u32 = 1 << 4;
+const P_MAX_SIZE: u32 = 1 << 5;
+const P_RESIZE_INC: u32 = 1 << 6;
+const P_ASPECT: u32 = 1 << 7;
+const P_BASE_SIZE: u32 = 1 << 8;
+
+/// Where a window stays along one axis when its size hints make it smaller (or larger) than its tiling
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Align {
+    /// at the left or top edge
+    #[default]
+    Start,
+    Center,
+    /// at the right or bottom edge
+    End,
+}
+
+impl Align {
+    /// The start of a span of `len` pixels, aligned in the span of `tile_len` pixels at `tile_start`
+    fn place(&self, tile_start: i32, tile_len: u32, len: u32) -> i32 {
+        let room = tile_len as i32 - len as i32;
+        match self {
+            Align::Start => tile_start,
+            Align::Center => tile_start + room / 2,
+            Align::End => tile_start + room,
+        }
+    }
+
+    /// Start if the span touches the start of the area, End if it touches only the end, and Center otherwise
+    fn auto(start: i32, end: i32, area_start: i32, area_end: i32) -> Align {
+        if start <= area_start {
+            Align::Start
+        } else if end >= area_end {
+            Align::End
+        } else {
+            Align::Center
+        }
+    }
+}
+
+/// The edge (or the corner, or the centre) of its tiling where a window stays, e.g. `top-right`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Anchor {
+    pub horizontal: Align,
+    pub vertical: Align,
+}
+
+impl Anchor {
+    /// Parses `top-left`, `top`, `top-right`, `left`, `center`, `right`, `bottom-left`, `bottom` or `bottom-right`
+    pub fn parse(name: &str) -> Result<Anchor, String> {
+        use Align::*;
+        let (horizontal, vertical) = match name {
+            "top-left" => (Start, Start),
+            "top" => (Center, Start),
+            "top-right" => (End, Start),
+            "left" => (Start, Center),
+            "center" => (Center, Center),
+            "right" => (End, Center),
+            "bottom-left" => (Start, End),
+            "bottom" => (Center, End),
+            "bottom-right" => (End, End),
+            _ => return Err(format!("bad anchor {:?}", name)),
+        };
+        Ok(Anchor {
+            horizontal,
+            vertical,
+        })
+    }
+
+    /// The anchor for a tiling `rect` in `area`: the edges of the area which the tiling touches, so that
+    /// a window at the right edge of the screen stays there, and a window touching neither edge stays in the middle
+    pub fn auto(rect: &Rect, area: &Rect) -> Anchor {
+        Anchor {
+            horizontal: Align::auto(rect.x, rect.right(), area.x, area.right()),
+            vertical: Align::auto(rect.y, rect.bottom(), area.y, area.bottom()),
+        }
+    }
+
+    /// A rectangle of `width` x `height` inside (or around) `rect`, at the anchor
+    pub fn place(&self, rect: &Rect, width: u32, height: u32) -> Rect {
+        Rect {
+            x: self.horizontal.place(rect.x, rect.width, width),
+            y: self.vertical.place(rect.y, rect.height, height),
+            width,
+            height,
+        }
+    }
+}
+
+/// The sizes which a client window accepts, from its `WM_NORMAL_HINTS`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeHints {
+    pub min: (u32, u32),
+    /// 0 for no maximum
+    pub max: (u32, u32),
+    /// e.g. the size of a character in a terminal; 0 or 1 for any size
+    pub increment: (u32, u32),
+    pub base: Option<(u32, u32)>,
+    /// the smallest and the largest width / height, as fractions (numerator, denominator)
+    pub aspect: Option<((u32, u32), (u32, u32))>,
+}
+
+impl SizeHints {
+    /// From the cardinals of the `WM_NORMAL_HINTS` property; only the fields flagged as present are used
+    pub fn from_cardinals(hints: &[u32]) -> SizeHints {
+        let field = |flag: u32, i: usize| -> Option<(u32, u32)> {
+            if hints.first().is_some_and(|flags| flags & flag != 0) && hints.len() > i + 1 {
+                Some((hints[i], hints[i + 1]))
+            } else {
+                None
+            }
+        };
+        let base = field(P_BASE_SIZE, 15);
+        SizeHints {
+            // without a minimum size, the base size is the minimum
+            min: field(P_MIN_SIZE, 5).or(base).unwrap_or((0, 0)),
+            max: field(P_MAX_SIZE, 7).unwrap_or((0, 0)),
+            increment: field(P_RESIZE_INC, 9).unwrap_or((0, 0)),
+            base,
+            aspect: field(P_ASPECT, 11).zip(field(P_ASPECT, 13)),
+        }
+    }
+
+    /// The largest size up to `width` x `height` which the window accepts; larger than that only if the minimum size is
+    pub fn fit(&self, width: u32, height: u32) -> (u32, u32) {
+        let (mut w, mut h) = (width, height);
+        if self.max.0 > 0 {
+            w = w.min(self.max.0);
+        }
+        if self.max.1 > 0 {
+            h = h.min(self.max.1);
+        }
+        // the aspect ratio is that of the size beyond the base size
+        let base = self.base.unwrap_or((0, 0));
+        if let Some(((min_num, min_den), (max_num, max_den))) = self.aspect {
+            let (aw, ah) = (
+                w.saturating_sub(base.0) as u64,
+                h.saturating_sub(base.1) as u64,
+            );
+            if max_den > 0 && max_num > 0 && aw * max_den as u64 > ah * max_num as u64 {
+                w = base.0 + (ah * max_num as u64 / max_den as u64) as u32;
+            } else if min_den > 0 && min_num > 0 && ah * min_num as u64 > aw * min_den as u64 {
+                h = base.1 + (aw * min_den as u64 / min_num as u64) as u32;
+            }
+        }
+        // the increments count from the base size, or else from the minimum size
+        let from = self.base.unwrap_or(self.min);
+        let step = |len: u32, from: u32, inc: u32| -> u32 {
+            if inc > 1 && len > from {
+                from + (len - from) / inc * inc
+            } else {
+                len
+            }
+        };
+        w = step(w, from.0, self.increment.0);
+        h = step(h, from.1, self.increment.1);
+        (w.max(self.min.0).max(1), h.max(self.min.1).max(1))
+    }
+
+    /// The client rectangle `rect`, resized to a size which the window accepts and placed at `anchor`
+    pub fn fit_rect(&self, rect: &Rect, anchor: Anchor) -> Rect {
+        let (width, height) = self.fit(rect.width, rect.height);
+        anchor.place(rect, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: Rect = Rect {
+        x: 100,
+        y: 50,
+        width: 805,
+        height: 603,
+    };
+
+    #[test]
+    fn parses_anchors() {
+        assert_eq!(
+            Anchor::parse("bottom-right").unwrap(),
+            Anchor {
+                horizontal: Align::End,
+                vertical: Align::End
+            }
+        );
+        assert_eq!(
+            Anchor::parse("top").unwrap(),
+            Anchor {
+                horizontal: Align::Center,
+                vertical: Align::Start
+            }
+        );
+        assert!(Anchor::parse("middle").is_err());
+    }
+
+    #[test]
+    fn automatic_anchors() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 1000,
+            height: 800,
+        };
+        let right_half = Rect {
+            x: 500,
+            width: 500,
+            ..area
+        };
+        assert_eq!(
+            Anchor::auto(&right_half, &area),
+            Anchor {
+                horizontal: Align::End,
+                vertical: Align::Start
+            }
+        );
+        assert_eq!(
+            Anchor::auto(&TILE, &area),
+            Anchor {
+                horizontal: Align::Center,
+                vertical: Align::Center
+            }
+        );
+    }
+
+    #[test]
+    fn reads_the_flagged_fields() {
+        let mut cardinals = vec![0; 18];
+        cardinals[0] = P_MIN_SIZE | P_RESIZE_INC | P_BASE_SIZE;
+        cardinals[5..7].copy_from_slice(&[20, 10]);
+        cardinals[7..9].copy_from_slice(&[400, 300]);
+        cardinals[9..11].copy_from_slice(&[8, 16]);
+        cardinals[15..17].copy_from_slice(&[4, 2]);
+        let hints = SizeHints::from_cardinals(&cardinals);
+        assert_eq!(hints.min, (20, 10));
+        // the maximum size is not flagged
+        assert_eq!(hints.max, (0, 0));
+        assert_eq!(hints.increment, (8, 16));
+        assert_eq!(hints.base, Some((4, 2)));
+        assert_eq!(hints.aspect, None);
+        assert_eq!(SizeHints::from_cardinals(&[]), SizeHints::default());
+    }
+
+    #[test]
+    fn fits_increments_from_the_base_size() {
+        let terminal = SizeHints {
+            increment: (8, 16),
+            base: Some((4, 2)),
+            ..SizeHints::default()
+        };
+        assert_eq!(terminal.fit(805, 603), (804, 594));
+    }
+
+    #[test]
+    fn fits_minimum_and_maximum_sizes() {
+        let hints = SizeHints {
+            min: (300, 200),
+            max: (600, 400),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.fit(805, 603), (600, 400));
+        assert_eq!(hints.fit(100, 100), (300, 200));
+    }
+
+    #[test]
+    fn fits_the_aspect_ratio() {
+        let square = SizeHints {
+            aspect: Some(((1, 1), (1, 1))),
+            ..SizeHints::default()
+        };
+        assert_eq!(square.fit(805, 603), (603, 603));
+        assert_eq!(square.fit(400, 700), (400, 400));
+    }
+
+    #[test]
+    fn places_at_the_anchor() {
+        let hints = SizeHints {
+            max: (605, 403),
+            ..SizeHints::default()
+        };
+        let placed = |name: &str| hints.fit_rect(&TILE, Anchor::parse(name).unwrap());
+        assert_eq!(
+            placed("top-left"),
+            Rect {
+                x: 100,
+                y: 50,
+                width: 605,
+                height: 403
+            }
+        );
+        assert_eq!(
+            placed("center"),
+            Rect {
+                x: 200,
+                y: 150,
+                width: 605,
+                height: 403
+            }
+        );
+        assert_eq!(
+            placed("bottom-right"),
+            Rect {
+                x: 300,
+                y: 250,
+                width: 605,
+                height: 403
+            }
+        );
+        assert_eq!(
+            SizeHints::default().fit_rect(&TILE, Anchor::default()),
+            TILE
+        );
+    }
+}
@@ -0,0 +1,1007 @@
+use crate::size_hints::Anchor;
+use crate::WintError;
+use std::fs::File;
+use std::path::Path;
+
+/// The `<display>` section used when the current resolution is not listed
+pub const DEFAULT_DISPLAY: &str = "default";
+
+#[derive(Debug, Deserialize)]
+pub struct WindowSimple {
+    #[serde(rename = "@nick", default)]
+    pub nick: String,
+    #[serde(rename = "@geometry", default)]
+    pub geometry: String,
+    #[serde(rename = "@frame", default)]
+    pub frame: FrameMode,
+    /// where the window stays when its size hints do not let it fill the tiling; the `anchor` of `<displays>` if empty
+    #[serde(rename = "@anchor", default)]
+    pub anchor: String,
+    /// the geometry is where the window goes, without gap and outer margin, e.g. when captured with `wint --capture`
+    #[serde(rename = "@exact", default)]
+    pub exact: bool,
+}
+
+/// What the geometry of a tiling describes
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+pub enum FrameMode {
+    /// the window together with the decorations drawn by the window manager
+    #[default]
+    #[serde(rename = "outer")]
+    Outer,
+    /// the window without decorations
+    #[serde(rename = "client")]
+    Client,
+}
+
+impl FrameMode {
+    /// The rectangle of the client window for the tiling `rect`, given the frame extents
+    /// (left, right, top, bottom) from `_NET_FRAME_EXTENTS`
+    pub fn client_rect(&self, rect: &Rect, extents: [u32; 4]) -> Rect {
+        match self {
+            FrameMode::Client => *rect,
+            FrameMode::Outer => {
+                let [left, right, top, bottom] = extents;
+                Rect {
+                    x: rect.x + left as i32,
+                    y: rect.y + top as i32,
+                    width: rect.width.saturating_sub(left + right).max(1),
+                    height: rect.height.saturating_sub(top + bottom).max(1),
+                }
+            }
+        }
+    }
+}
+
+/// Where a window goes, according to `tilings.xml`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub rect: Rect,
+    pub frame: FrameMode,
+    pub rule: Rule,
+    pub anchor: Anchor,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Display {
+    #[serde(rename = "@resolution", default)]
+    pub resolution: String,
+
+    /// if set, the section is only for the monitor on this output (e.g. `HDMI-1`)
+    #[serde(rename = "@output", default)]
+    pub output: String,
+
+    /// the `gap` of `<displays>` for this section
+    #[serde(rename = "@gap", default)]
+    pub gap: Option<u32>,
+
+    /// the `outer-margin` of `<displays>` for this section
+    #[serde(rename = "@outer-margin", default)]
+    pub outer_margin: Option<u32>,
+
+    #[serde(rename = "window", default)]
+    pub windows: Vec<WindowSimple>,
+}
+
+/// One window of a `<layout>`: a window matching `class` and `title` gets the tiling `nick`
+#[derive(Debug, Deserialize)]
+pub struct Role {
+    #[serde(rename = "@nick", default)]
+    pub nick: String,
+    /// the class of the window, exactly
+    #[serde(rename = "@class", default)]
+    pub class: String,
+    /// a part of the title of the window
+    #[serde(rename = "@title", default)]
+    pub title: String,
+}
+
+impl Role {
+    /// Whether the role says which windows it wants; if not, it takes the next hinted window
+    pub fn has_rules(&self) -> bool {
+        !self.class.is_empty() || !self.title.is_empty()
+    }
+
+    pub fn matches(&self, class: &str, title: &str) -> bool {
+        (self.class.is_empty() || self.class == class)
+            && (self.title.is_empty() || title.contains(&self.title))
+    }
+}
+
+/// Several windows tiled at once, e.g. `<layout name="latex">`
+#[derive(Debug, Deserialize)]
+pub struct Layout {
+    #[serde(rename = "@name", default)]
+    pub name: String,
+    #[serde(rename = "role", default)]
+    pub roles: Vec<Role>,
+}
+
+impl Layout {
+    /// Gives each role a window from `windows` (id, class, title, in the order of the charhints):
+    /// first the roles with a class or a title take the first matching window, and then the other roles
+    /// take the remaining windows in order. Roles without a window are left out.
+    pub fn assign<'a, T: Copy + PartialEq>(
+        &'a self,
+        windows: &[(T, &str, &str)],
+    ) -> Vec<(T, &'a str)> {
+        let mut taken: Vec<Option<T>> = vec![None; self.roles.len()];
+        let free = |taken: &Vec<Option<T>>, w: &T| !taken.contains(&Some(*w));
+        for (i, role) in self.roles.iter().enumerate() {
+            if role.has_rules() {
+                taken[i] = windows
+                    .iter()
+                    .find(|(w, class, title)| free(&taken, w) && role.matches(class, title))
+                    .map(|(w, _, _)| *w);
+            }
+        }
+        for (i, role) in self.roles.iter().enumerate() {
+            if !role.has_rules() {
+                taken[i] = windows
+                    .iter()
+                    .find(|(w, _, _)| free(&taken, w))
+                    .map(|(w, _, _)| *w);
+            }
+        }
+        self.roles
+            .iter()
+            .zip(taken)
+            .filter_map(|(role, w)| w.map(|w| (w, role.nick.as_str())))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(from = "TilingsFile")]
+pub struct Displays {
+    pub items: Vec<Display>,
+    pub layouts: Vec<Layout>,
+    /// the pixels between adjacent windows, unless the section of the monitor sets its own
+    pub gap: u32,
+    /// the pixels between the windows and the edges of the work area, unless the section of the monitor sets its own
+    pub outer_margin: u32,
+    /// the part of the width for the master window of `:tall`
+    pub master_ratio: Option<f64>,
+    /// the grid of the cells in commands like `a@1:0`, as `COLUMNSxROWS`
+    pub grid: Option<String>,
+    /// where windows stay when their size hints do not let them fill their tilings; `auto` if empty
+    pub anchor: String,
+}
+
+/// The elements of `tilings.xml` in the order of the file, so that `<display>` and `<layout>` can be mixed
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename = "displays", default)]
+struct TilingsFile {
+    #[serde(rename = "@gap", default)]
+    gap: u32,
+    #[serde(rename = "@outer-margin", default)]
+    outer_margin: u32,
+    #[serde(rename = "@master-ratio", default)]
+    master_ratio: Option<f64>,
+    #[serde(rename = "@grid", default)]
+    grid: Option<String>,
+    #[serde(rename = "@anchor", default)]
+    anchor: String,
+    #[serde(rename = "#content", default)]
+    elements: Vec<TilingsElement>,
+}
+
+#[derive(Debug, Deserialize)]
+enum TilingsElement {
+    #[serde(rename = "display")]
+    Display(Display),
+    #[serde(rename = "layout")]
+    Layout(Layout),
+}
+
+impl From<TilingsFile> for Displays {
+    fn from(file: TilingsFile) -> Displays {
+        let mut tilings = Displays {
+            gap: file.gap,
+            outer_margin: file.outer_margin,
+            master_ratio: file.master_ratio,
+            grid: file.grid,
+            anchor: file.anchor,
+            ..Displays::default()
+        };
+        for element in file.elements {
+            match element {
+                TilingsElement::Display(d) => tilings.items.push(d),
+                TilingsElement::Layout(l) => tilings.layouts.push(l),
+            }
+        }
+        tilings
+    }
+}
+
+/// Room around the tilings: `gap` pixels between adjacent windows, and `outer_margin` pixels between the windows
+/// and the edges of the work area
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Spacing {
+    pub gap: u32,
+    pub outer_margin: u32,
+}
+
+impl Spacing {
+    /// The part of `area` inside the outer margin
+    pub fn inner(&self, area: &Rect) -> Rect {
+        let m = self.outer_margin;
+        Rect {
+            x: area.x + m as i32,
+            y: area.y + m as i32,
+            width: area.width.saturating_sub(2 * m).max(1),
+            height: area.height.saturating_sub(2 * m).max(1),
+        }
+    }
+
+    /// Shrinks a tiling `rect` in `area`: the edges at (or beyond) the edges of the area move in by the outer margin,
+    /// and the others by half the gap, so that tiles which meet end up `gap` pixels apart
+    pub fn apply(&self, rect: &Rect, area: &Rect) -> Rect {
+        let (m, near, far) = (
+            self.outer_margin as i32,
+            (self.gap - self.gap / 2) as i32,
+            (self.gap / 2) as i32,
+        );
+        let inner = self.inner(area);
+        let (x, y) = (
+            if rect.x <= area.x {
+                inner.x
+            } else {
+                rect.x + near
+            },
+            if rect.y <= area.y {
+                inner.y
+            } else {
+                rect.y + near
+            },
+        );
+        let (right, bottom) = (
+            if rect.right() >= area.right() {
+                area.right() - m
+            } else {
+                rect.right() - far
+            },
+            if rect.bottom() >= area.bottom() {
+                area.bottom() - m
+            } else {
+                rect.bottom() - far
+            },
+        );
+        Rect {
+            x,
+            y,
+            width: (right - x).max(1) as u32,
+            height: (bottom - y).max(1) as u32,
+        }
+    }
+}
+
+/// A rectangle on the screen, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Parses the desktop geometry `WIDTHxHEIGHT`, as given by `get_wm_data`
+    pub fn from_resolution(resolution: &str) -> Option<Rect> {
+        let (w, h) = resolution.split_once('x')?;
+        Some(Rect {
+            x: 0,
+            y: 0,
+            width: w.trim().parse().ok()?,
+            height: h.trim().parse().ok()?,
+        })
+    }
+
+    /// The size as `WIDTHxHEIGHT`, the way resolutions are written in `tilings.xml`
+    pub fn resolution(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+impl Rect {
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    /// The area of the intersection with `other`, in square pixels
+    pub fn overlap(&self, other: &Rect) -> u64 {
+        let w = self.right().min(other.right()) - self.x.max(other.x);
+        let h = self.bottom().min(other.bottom()) - self.y.max(other.y);
+        if w <= 0 || h <= 0 {
+            return 0;
+        }
+        w as u64 * h as u64
+    }
+
+    /// Moves `self` from the area `from` to the area `to`, keeping its size (as far as it fits)
+    /// and its relative position, e.g. a window at the right edge stays at the right edge
+    pub fn moved(&self, from: &Rect, to: &Rect) -> Rect {
+        let (width, height) = (self.width.min(to.width), self.height.min(to.height));
+        Rect {
+            x: move_span(
+                self.x, self.width, from.x, from.width, to.x, to.width, width,
+            ),
+            y: move_span(
+                self.y,
+                self.height,
+                from.y,
+                from.height,
+                to.y,
+                to.height,
+                height,
+            ),
+            width,
+            height,
+        }
+    }
+
+    /// The part of `self` inside `area`; unchanged if they do not intersect
+    pub fn clip(&self, area: &Rect) -> Rect {
+        let (x, y) = (self.x.max(area.x), self.y.max(area.y));
+        let (right, bottom) = (
+            self.right().min(area.right()),
+            self.bottom().min(area.bottom()),
+        );
+        if right <= x || bottom <= y {
+            return *self;
+        }
+        Rect {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        }
+    }
+}
+
+/// The new start of a span of length `len` (formerly `old_len`, at `start`) moved along one axis
+/// from `from_start..from_start+from_len` to `to_start..to_start+to_len`
+fn move_span(
+    start: i32,
+    old_len: u32,
+    from_start: i32,
+    from_len: u32,
+    to_start: i32,
+    to_len: u32,
+    len: u32,
+) -> i32 {
+    let room_from = from_len.saturating_sub(old_len) as f64;
+    let room_to = to_len.saturating_sub(len) as f64;
+    let fraction = if room_from > 0.0 {
+        ((start - from_start) as f64 / room_from).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    to_start + (fraction * room_to).round() as i32
+}
+
+/// One component of a geometry in `tilings.xml`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coord {
+    Pixels(i32),
+    /// a fraction of the width or height of the work area
+    Fraction(f64),
+}
+
+/// A geometry `x,y,width,height` from `tilings.xml`.
+///
+/// Each component is either a number of pixels, or relative to the work area:
+/// a percentage (`50%`) or a fraction (`1/2` or `0.5`). In a geometry with at least one
+/// relative component, plain numbers are fractions as well, up to 1 (so `0,0,1/2,1` is the left half),
+/// and pixels must be written with the suffix `px`. A geometry of plain numbers
+/// is in pixels from the top left corner of the monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub x: Coord,
+    pub y: Coord,
+    pub width: Coord,
+    pub height: Coord,
+}
+
+fn parse_coord(s: &str, relative: bool) -> Result<Coord, String> {
+    let s = s.trim();
+    let bad = || format!("bad geometry component: {:?}", s);
+    if let Some(percent) = s.strip_suffix('%') {
+        let p = percent.trim().parse::<f64>().map_err(|_| bad())?;
+        Ok(Coord::Fraction(p / 100.0))
+    } else if let Some((num, den)) = s.split_once('/') {
+        let num = num.trim().parse::<f64>().map_err(|_| bad())?;
+        let den = den.trim().parse::<f64>().map_err(|_| bad())?;
+        if den == 0.0 {
+            return Err(bad());
+        }
+        Ok(Coord::Fraction(num / den))
+    } else if let Some(px) = s.strip_suffix("px") {
+        Ok(Coord::Pixels(px.trim().parse::<i32>().map_err(|_| bad())?))
+    } else if relative {
+        let f = s.parse::<f64>().map_err(|_| bad())?;
+        // a plain number above 1 in a relative geometry is most likely meant as pixels
+        if f > 1.0 {
+            return Err(format!(
+                "{} in a relative geometry: write pixels as {}px",
+                bad(),
+                s
+            ));
+        }
+        Ok(Coord::Fraction(f))
+    } else {
+        Ok(Coord::Pixels(s.parse::<i32>().map_err(|_| bad())?))
+    }
+}
+
+impl Geometry {
+    pub fn parse(geometry: &str) -> Result<Geometry, String> {
+        let parts: Vec<&str> = geometry.split(',').collect();
+        if parts.len() != 4 {
+            return Err(format!(
+                "geometry {:?} should be of the form x,y,width,height",
+                geometry
+            ));
+        }
+        let relative = parts
+            .iter()
+            .any(|p| p.contains('%') || p.contains('/') || p.contains('.'));
+        Ok(Geometry {
+            x: parse_coord(parts[0], relative)?,
+            y: parse_coord(parts[1], relative)?,
+            width: parse_coord(parts[2], relative)?,
+            height: parse_coord(parts[3], relative)?,
+        })
+    }
+
+    pub fn is_relative(&self) -> bool {
+        [self.x, self.y, self.width, self.height]
+            .iter()
+            .any(|c| matches!(c, Coord::Fraction(_)))
+    }
+
+    /// The rectangle on the screen, cut to fit into `area`; relative geometries are placed in `area`,
+    /// while pixel geometries are counted from the corner of the `monitor`
+    pub fn resolve(&self, monitor: &Rect, area: &Rect) -> Rect {
+        if !self.is_relative() {
+            return Rect {
+                x: monitor.x + pixels(self.x),
+                y: monitor.y + pixels(self.y),
+                width: pixels(self.width).max(1) as u32,
+                height: pixels(self.height).max(1) as u32,
+            }
+            .clip(area);
+        }
+        let (x, width) = resolve_span(self.x, self.width, area.x, area.width);
+        let (y, height) = resolve_span(self.y, self.height, area.y, area.height);
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+        .clip(area)
+    }
+}
+
+fn pixels(c: Coord) -> i32 {
+    match c {
+        Coord::Pixels(p) => p,
+        Coord::Fraction(f) => f.round() as i32,
+    }
+}
+
+/// Resolves start and length along one axis; the far edge is rounded rather than the length,
+/// so that tiles like `0,0,1/3,1` and `1/3,0,1/3,1` meet exactly
+fn resolve_span(start: Coord, length: Coord, origin: i32, extent: u32) -> (i32, u32) {
+    let at = |c: Coord| -> f64 {
+        match c {
+            Coord::Pixels(p) => p as f64,
+            Coord::Fraction(f) => f * extent as f64,
+        }
+    };
+    let from = at(start).round() as i32;
+    let to = (at(start) + at(length)).round() as i32;
+    (origin + from, (to - from).max(1) as u32)
+}
+
+/// Where the geometry of a nick was found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// in the section for the output of the monitor
+    Output(String),
+    /// in the section for the current resolution
+    Exact,
+    /// in the `default` section
+    Default,
+    /// in the section for another resolution, scaled proportionally
+    Scaled(String),
+    /// not from `tilings.xml`: the window keeps its size and position, but on another monitor
+    Moved,
+    /// not from `tilings.xml`: the geometry which the window had before it was tiled
+    Undone,
+    /// not from `tilings.xml`: the geometry saved in the session of this name
+    Session(String),
+    /// not from `tilings.xml`: computed by a generated layout, e.g. `tall`
+    Generated(String),
+    /// not from `tilings.xml`: cells of a grid, e.g. `@2x2/tl`
+    Cell(String),
+    /// not from `tilings.xml`: the geometry of another window, which was swapped or rotated with this one
+    Swapped,
+    /// not from `tilings.xml`: the geometry of the window, mirrored left to right
+    Mirrored,
+    /// not from `tilings.xml`: moved and resized with the arrow keys, in `wint :move a`
+    Keyboard,
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Rule::Output(name) => write!(f, "output {}", name),
+            Rule::Exact => write!(f, "exact resolution"),
+            Rule::Default => write!(f, "the {:?} section", DEFAULT_DISPLAY),
+            Rule::Scaled(from) => write!(f, "scaled from {}", from),
+            Rule::Moved => write!(f, "the current geometry"),
+            Rule::Undone => write!(f, "the undo history"),
+            Rule::Session(name) => write!(f, "the session {:?}", name),
+            Rule::Generated(name) => write!(f, "the generated layout :{}", name),
+            Rule::Cell(spec) => write!(f, "the grid cells {}", spec),
+            Rule::Swapped => write!(f, "the geometry of another window"),
+            Rule::Mirrored => write!(f, "the mirrored geometry"),
+            Rule::Keyboard => write!(f, "the arrow keys"),
+        }
+    }
+}
+
+impl Geometry {
+    /// Multiplies the pixel components; relative components do not depend on the resolution
+    pub fn scaled(&self, sx: f64, sy: f64) -> Geometry {
+        let scale = |c: Coord, s: f64| match c {
+            Coord::Pixels(p) => Coord::Pixels((p as f64 * s).round() as i32),
+            fraction => fraction,
+        };
+        Geometry {
+            x: scale(self.x, sx),
+            y: scale(self.y, sy),
+            width: scale(self.width, sx),
+            height: scale(self.height, sy),
+        }
+    }
+}
+
+impl Displays {
+    pub fn read(xml_path: &Path) -> Result<Displays, WintError> {
+        let tilings: Displays = serde_xml_rs::from_reader(File::open(xml_path)?)?;
+        Ok(tilings)
+    }
+
+    pub fn layout(&self, name: &str) -> Option<&Layout> {
+        self.layouts.iter().find(|l| l.name == name)
+    }
+
+    fn section(&self, resolution: &str) -> Option<&Display> {
+        self.items
+            .iter()
+            .find(|disp| disp.output.is_empty() && disp.resolution == resolution)
+    }
+
+    fn output_section(&self, output: &str) -> Option<&Display> {
+        self.items.iter().find(|disp| disp.output == output)
+    }
+
+    /// The listed resolutions, nearest to `resolution` first
+    fn nearest(&self, resolution: &str) -> Vec<(&Display, Rect)> {
+        let target = match Rect::from_resolution(resolution) {
+            Some(r) => r,
+            None => return vec![],
+        };
+        let mut listed: Vec<(&Display, Rect)> = self
+            .items
+            .iter()
+            .filter(|disp| disp.output.is_empty())
+            .filter_map(|disp| Rect::from_resolution(&disp.resolution).map(|r| (disp, r)))
+            .filter(|(_, r)| r.width > 0 && r.height > 0)
+            .collect();
+        let distance = |r: &Rect| {
+            let dw = r.width as f64 - target.width as f64;
+            let dh = r.height as f64 - target.height as f64;
+            dw * dw + dh * dh
+        };
+        listed.sort_by(|a, b| distance(&a.1).total_cmp(&distance(&b.1)));
+        listed
+    }
+
+    /// The nicks which can be used on the monitor connected to `output`, in the order in which `lookup` finds them
+    pub fn nicks(&self, output: &str, resolution: &str) -> Vec<String> {
+        let mut sections: Vec<&Display> = vec![];
+        sections.extend(self.output_section(output));
+        sections.extend(self.section(resolution));
+        sections.extend(self.section(DEFAULT_DISPLAY));
+        sections.extend(self.nearest(resolution).into_iter().map(|(disp, _)| disp));
+        let mut nicks: Vec<String> = vec![];
+        for disp in sections {
+            for w in disp.windows.iter() {
+                if !nicks.contains(&w.nick) {
+                    nicks.push(w.nick.clone());
+                }
+            }
+        }
+        nicks
+    }
+
+    /// The gap and the outer margin on the monitor connected to `output`: those of the section which serves it
+    /// (as in `rule_for`), or else those of `<displays>`
+    pub fn spacing(&self, output: &str, resolution: &str) -> Spacing {
+        let section = self
+            .output_section(output)
+            .or_else(|| self.section(resolution))
+            .or_else(|| self.section(DEFAULT_DISPLAY))
+            .or_else(|| self.nearest(resolution).first().map(|(disp, _)| *disp));
+        Spacing {
+            gap: section.and_then(|disp| disp.gap).unwrap_or(self.gap),
+            outer_margin: section
+                .and_then(|disp| disp.outer_margin)
+                .unwrap_or(self.outer_margin),
+        }
+    }
+
+    /// The anchor of a window tiled as `rect` in `area`: `window_anchor` if set, or else the `anchor` of `<displays>`;
+    /// `auto` anchors the window to the edges of the area which the tiling touches
+    pub fn anchor(&self, window_anchor: &str, rect: &Rect, area: &Rect) -> Result<Anchor, String> {
+        let name = if window_anchor.is_empty() {
+            &self.anchor
+        } else {
+            window_anchor
+        };
+        match name {
+            "" | "auto" => Ok(Anchor::auto(rect, area)),
+            _ => Anchor::parse(name),
+        }
+    }
+
+    /// Which rule applies to a monitor as a whole (for the message in the dialog)
+    pub fn rule_for(&self, output: &str, resolution: &str) -> Option<Rule> {
+        if self.output_section(output).is_some() {
+            Some(Rule::Output(output.to_string()))
+        } else if self.section(resolution).is_some() {
+            Some(Rule::Exact)
+        } else if self.section(DEFAULT_DISPLAY).is_some() {
+            Some(Rule::Default)
+        } else {
+            self.nearest(resolution)
+                .first()
+                .map(|(disp, _)| Rule::Scaled(disp.resolution.clone()))
+        }
+    }
+
+    /// Finds the geometry of `nick` on the monitor connected to `output`: first in the section
+    /// for the output, then for the exact resolution of the monitor, then in the `default` section,
+    /// and then for the nearest listed resolution, scaled proportionally
+    pub fn lookup<'a>(
+        &'a self,
+        nick: &str,
+        output: &str,
+        resolution: &str,
+    ) -> Result<Option<(Geometry, &'a WindowSimple, Rule)>, WintError> {
+        let find = |disp: &'a Display| -> Result<Option<(Geometry, &'a WindowSimple)>, WintError> {
+            match disp.windows.iter().find(|w| w.nick == nick) {
+                Some(w) => Geometry::parse(&w.geometry)
+                    .map(|g| Some((g, w)))
+                    .map_err(|e| {
+                        let section = if disp.output.is_empty() {
+                            &disp.resolution
+                        } else {
+                            &disp.output
+                        };
+                        WintError::BadGeometry(format!(
+                            "nick {:?} in display {:?}: {}",
+                            nick, section, e
+                        ))
+                    }),
+                None => Ok(None),
+            }
+        };
+        if let Some(disp) = self.output_section(output) {
+            if let Some((g, window)) = find(disp)? {
+                return Ok(Some((g, window, Rule::Output(output.to_string()))));
+            }
+        }
+        if let Some(disp) = self.section(resolution) {
+            if let Some((g, window)) = find(disp)? {
+                return Ok(Some((g, window, Rule::Exact)));
+            }
+        }
+        if let Some(disp) = self.section(DEFAULT_DISPLAY) {
+            if let Some((g, window)) = find(disp)? {
+                return Ok(Some((g, window, Rule::Default)));
+            }
+        }
+        let target = match Rect::from_resolution(resolution) {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        for (disp, from) in self.nearest(resolution) {
+            if let Some((g, window)) = find(disp)? {
+                let sx = target.width as f64 / from.width as f64;
+                let sy = target.height as f64 / from.height as f64;
+                return Ok(Some((
+                    g.scaled(sx, sy),
+                    window,
+                    Rule::Scaled(disp.resolution.clone()),
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The placement of `nick` on the monitor `monitor` connected to `output`,
+/// where `area` is the part of the monitor not covered by panels; with room for the gap and the outer margin
+pub fn get_geometry(
+    xml_path: &Path,
+    nick: String,
+    output: &str,
+    monitor: &Rect,
+    area: &Rect,
+) -> Result<Option<Placement>, WintError> {
+    let tilings = Displays::read(xml_path)?;
+    let resolution = monitor.resolution();
+    let spacing = tilings.spacing(output, &resolution);
+    let (g, window, rule) = match tilings.lookup(&nick, output, &resolution)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let rect = g.resolve(monitor, area);
+    let anchor = tilings
+        .anchor(&window.anchor, &rect, area)
+        .map_err(|e| WintError::BadGeometry(format!("nick {:?}: {}", nick, e)))?;
+    Ok(Some(Placement {
+        rect: if window.exact {
+            rect
+        } else {
+            spacing.apply(&rect, area)
+        },
+        frame: window.frame,
+        rule,
+        anchor,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect::new(0, 0, 1000, 800);
+
+    fn resolved(geometry: &str, area: &Rect) -> Rect {
+        Geometry::parse(geometry).unwrap().resolve(area, area)
+    }
+
+    #[test]
+    fn parses_relative_forms() {
+        let half = Geometry {
+            x: Coord::Fraction(0.5),
+            y: Coord::Fraction(0.0),
+            width: Coord::Fraction(0.5),
+            height: Coord::Fraction(1.0),
+        };
+        assert_eq!(Geometry::parse("50%,0%,50%,100%").unwrap(), half);
+        assert_eq!(Geometry::parse("1/2,0,1/2,1").unwrap(), half);
+        assert_eq!(Geometry::parse("0.5,0,0.5,1").unwrap(), half);
+        assert_eq!(resolved("1/2,0,1/2,1", &AREA), Rect::new(500, 0, 500, 800));
+    }
+
+    #[test]
+    fn parses_pixels() {
+        let g = Geometry::parse("10,20,300,400").unwrap();
+        assert!(!g.is_relative());
+        assert_eq!(g.x, Coord::Pixels(10));
+        assert_eq!(
+            resolved("10,20,300,400", &AREA),
+            Rect::new(10, 20, 300, 400)
+        );
+        assert_eq!(
+            Geometry::parse("10px,20px,300px,400px").unwrap(),
+            Geometry::parse("10,20,300,400").unwrap()
+        );
+    }
+
+    #[test]
+    fn mixes_pixels_and_fractions() {
+        let g = Geometry::parse("10px,0,1/2,1").unwrap();
+        assert_eq!(g.x, Coord::Pixels(10));
+        assert_eq!(g.width, Coord::Fraction(0.5));
+        assert_eq!(resolved("10px,0,1/2,1", &AREA), Rect::new(10, 0, 500, 800));
+    }
+
+    #[test]
+    fn rejects_plain_pixels_in_relative_geometries() {
+        let e = Geometry::parse("0,0,1/2,883").unwrap_err();
+        assert!(e.contains("883px"), "{}", e);
+        assert!(Geometry::parse("0,0,1/2,x").is_err());
+        assert!(Geometry::parse("0,0,1/0,1").is_err());
+        assert!(Geometry::parse("0,0,1/2").is_err());
+    }
+
+    #[test]
+    fn adjacent_thirds_meet_exactly() {
+        let area = Rect::new(7, 0, 1001, 800);
+        let thirds: Vec<Rect> = ["0,0,1/3,1", "1/3,0,1/3,1", "2/3,0,1/3,1"]
+            .iter()
+            .map(|g| resolved(g, &area))
+            .collect();
+        assert_eq!(thirds[0].x, area.x);
+        assert_eq!(thirds[0].right(), thirds[1].x);
+        assert_eq!(thirds[1].right(), thirds[2].x);
+        assert_eq!(thirds[2].right(), area.right());
+    }
+
+    #[test]
+    fn clips_to_the_area() {
+        let area = Rect::new(0, 30, 1000, 770);
+        assert_eq!(
+            resolved("1/2,0,100%,1", &area),
+            Rect::new(500, 30, 500, 770)
+        );
+        let monitor = Rect::new(0, 0, 1000, 800);
+        let g = Geometry::parse("0,0,1200,800").unwrap();
+        assert_eq!(g.resolve(&monitor, &area), Rect::new(0, 30, 1000, 770));
+    }
+
+    #[test]
+    fn splits_the_gap_between_neighbours() {
+        let spacing = Spacing {
+            gap: 9,
+            outer_margin: 0,
+        };
+        let left = spacing.apply(&Rect::new(0, 0, 500, 800), &AREA);
+        let right = spacing.apply(&Rect::new(500, 0, 500, 800), &AREA);
+        assert_eq!(left, Rect::new(0, 0, 496, 800));
+        assert_eq!(right, Rect::new(505, 0, 495, 800));
+        assert_eq!(right.x - left.right(), 9);
+    }
+
+    #[test]
+    fn keeps_the_outer_margin_at_the_edges_of_the_area() {
+        let spacing = Spacing {
+            gap: 10,
+            outer_margin: 20,
+        };
+        assert_eq!(spacing.inner(&AREA), Rect::new(20, 20, 960, 760));
+        assert_eq!(spacing.apply(&AREA, &AREA), Rect::new(20, 20, 960, 760));
+        assert_eq!(
+            spacing.apply(&Rect::new(0, 400, 500, 400), &AREA),
+            Rect::new(20, 405, 475, 375)
+        );
+        // beyond the area counts as at its edge
+        assert_eq!(
+            spacing.apply(&Rect::new(-10, -10, 1100, 900), &AREA),
+            Rect::new(20, 20, 960, 760)
+        );
+    }
+
+    #[test]
+    fn exact_geometries_skip_the_spacing() {
+        let xml = r#"<?xml version="1.0"?>
+<displays gap="10" outer-margin="20">
+  <display resolution="1000x800">
+    <window nick="l" geometry="0,0,1/2,1"/>
+    <window nick="c" geometry="100,100,300,200" exact="true"/>
+  </display>
+</displays>"#;
+        let path = std::env::temp_dir().join(format!("wint-test-exact-{}.xml", std::process::id()));
+        std::fs::write(&path, xml).unwrap();
+        let rect = |nick: &str| {
+            get_geometry(&path, nick.to_string(), "HDMI-1", &AREA, &AREA)
+                .unwrap()
+                .unwrap()
+                .rect
+        };
+        let (l, c) = (rect("l"), rect("c"));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(l, Rect::new(20, 20, 475, 760));
+        assert_eq!(c, Rect::new(100, 100, 300, 200));
+    }
+
+    const DISPLAYS: &str = r#"<?xml version="1.0"?>
+<displays>
+  <display output="HDMI-1">
+    <window nick="a" geometry="0,0,1/2,1"/>
+  </display>
+  <display resolution="1000x800">
+    <window nick="a" geometry="0,0,1/3,1"/>
+    <window nick="b" geometry="0,0,500,400"/>
+  </display>
+  <display resolution="default">
+    <window nick="c" geometry="0,0,1,1/2"/>
+  </display>
+  <display resolution="2000x1600">
+    <window nick="d" geometry="100,200,1000,800"/>
+    <window nick="e" geometry="0,0,1/2,1"/>
+  </display>
+  <display resolution="500x400">
+    <window nick="d" geometry="10,20,100,80"/>
+    <window nick="bad" geometry="0,0,50x,400"/>
+  </display>
+</displays>"#;
+
+    fn displays() -> Displays {
+        serde_xml_rs::from_str(DISPLAYS).unwrap()
+    }
+
+    #[test]
+    fn looks_up_the_output_section_first() {
+        let displays = displays();
+        let (g, window, rule) = displays.lookup("a", "HDMI-1", "1000x800").unwrap().unwrap();
+        assert_eq!(rule, Rule::Output("HDMI-1".to_string()));
+        assert_eq!(window.geometry, "0,0,1/2,1");
+        assert_eq!(g.width, Coord::Fraction(0.5));
+        assert_eq!(
+            displays.rule_for("HDMI-1", "1000x800"),
+            Some(Rule::Output("HDMI-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn looks_up_the_exact_resolution_then_the_default_section() {
+        let displays = displays();
+        let (_, window, rule) = displays.lookup("a", "DP-1", "1000x800").unwrap().unwrap();
+        assert_eq!((window.geometry.as_str(), rule), ("0,0,1/3,1", Rule::Exact));
+        // from an output section, the nicks it does not have are looked up further
+        let (_, _, rule) = displays.lookup("b", "HDMI-1", "1000x800").unwrap().unwrap();
+        assert_eq!(rule, Rule::Exact);
+        let (_, _, rule) = displays.lookup("c", "DP-1", "1000x800").unwrap().unwrap();
+        assert_eq!(rule, Rule::Default);
+        assert_eq!(displays.rule_for("DP-1", "1000x800"), Some(Rule::Exact));
+        assert_eq!(displays.rule_for("DP-1", "1920x1080"), Some(Rule::Default));
+    }
+
+    #[test]
+    fn scales_from_the_nearest_resolution_which_has_the_nick() {
+        let displays = displays();
+        // 1000x800 is the nearest to 1200x900 but has no `d`, and 500x400 is nearer than 2000x1600
+        let (g, _, rule) = displays.lookup("d", "DP-1", "1200x900").unwrap().unwrap();
+        assert_eq!(rule, Rule::Scaled("500x400".to_string()));
+        assert_eq!(g, Geometry::parse("24,45,240,180").unwrap());
+        // relative geometries stay as they are
+        let (g, _, rule) = displays.lookup("e", "DP-1", "1200x900").unwrap().unwrap();
+        assert_eq!(rule, Rule::Scaled("2000x1600".to_string()));
+        assert_eq!(g, Geometry::parse("0,0,1/2,1").unwrap());
+
+        let without_default: Displays = serde_xml_rs::from_str(
+            r#"<displays><display resolution="2000x1600"><window nick="d" geometry="0,0,10,10"/></display></displays>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            without_default.rule_for("DP-1", "1200x900"),
+            Some(Rule::Scaled("2000x1600".to_string()))
+        );
+        assert_eq!(without_default.rule_for("DP-1", "unknown"), None);
+    }
+
+    #[test]
+    fn reports_bad_geometries() {
+        let displays = displays();
+        assert!(matches!(
+            displays.lookup("bad", "DP-1", "500x400"),
+            Err(WintError::BadGeometry(_))
+        ));
+        assert!(displays
+            .lookup("missing", "DP-1", "1000x800")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -19,11 +19,41 @@ use std::io::{BufRead, Write};
 use std::path::Path;
 use std::rc::Rc;
 use winterreise::{
-    check_css, check_tilings, get_conf, get_config_dir, get_wm_data, go_to_window, make_vbox,
-    Config, TMPFile,
+    check_css, check_tilings, get_conf, get_config_dir, get_desktop_names, get_wm_data,
+    go_to_window, make_vbox, most_recent_urgent, tmpfile_path, Config, DesktopDirection,
+    DesktopLayout, RowStyle,
 };
 use xcb_wm::ewmh;
 
+/// Keys `1`..`9` and `0` select desktops 1 to 10, and `F1`..`F12` select desktops 11 to 22
+fn desktop_for_key(keyval: u32) -> Option<u32> {
+    let (key_1, key_9) = (gdk_sys::GDK_KEY_1 as u32, gdk_sys::GDK_KEY_9 as u32);
+    let (key_f1, key_f12) = (gdk_sys::GDK_KEY_F1 as u32, gdk_sys::GDK_KEY_F12 as u32);
+    if keyval >= key_1 && keyval <= key_9 {
+        Some(keyval - key_1)
+    } else if keyval == gdk_sys::GDK_KEY_0 as u32 {
+        Some(9)
+    } else if keyval >= key_f1 && keyval <= key_f12 {
+        Some(keyval - key_f1 + 10)
+    } else {
+        None
+    }
+}
+
+fn direction_for_key(keyval: u32) -> Option<DesktopDirection> {
+    if keyval == gdk_sys::GDK_KEY_Right as u32 {
+        Some(DesktopDirection::Next)
+    } else if keyval == gdk_sys::GDK_KEY_Left as u32 {
+        Some(DesktopDirection::Previous)
+    } else if keyval == gdk_sys::GDK_KEY_Up as u32 {
+        Some(DesktopDirection::Up)
+    } else if keyval == gdk_sys::GDK_KEY_Down as u32 {
+        Some(DesktopDirection::Down)
+    } else {
+        None
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let clops = App::new("wmjump")
         .author("Andrei Mikhailov")
@@ -33,22 +63,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("only show windows on the current desktop")
                 .short("c"),
         )
+        .arg(
+            Arg::with_name("urgent")
+                .help("go straight to the window which most recently demanded attention")
+                .short("u")
+                .long("urgent"),
+        )
         .get_matches();
     let config_dir = get_config_dir();
     let conf: Config = get_conf()?;
-    let maxlen = conf.maxwidth;
-    let tmpfilename = match conf.tmpfile {
-        TMPFile::Custom(x) => format!("{}", x),
-        TMPFile::InXdgRuntime => match std::env::vars()
-            .into_iter()
-            .filter(|(k, _v)| k == "XDG_RUNTIME_DIR")
-            .next()
-        {
-            Some(x) => format!("{}/winterreise", x.1),
-            None => panic!("system does not have XDG_RUNTIME_DIR; please use custom <tmpfile><custom>...</custom></tmpfile> or <tmpfile><in_tmp/></custom> option in config"),
-        },
-        TMPFile::InTmp => String::from("/tmp/winterreise"),
-    };
+    let row_style = RowStyle::from_config(&conf);
+    let tmpfilename = tmpfile_path(&conf.tmpfile);
     let tmpfile = std::fs::OpenOptions::new()
         .read(true)
         .open(&tmpfilename)
@@ -74,7 +99,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .open(&tmpfilename)
         .unwrap();
     let tmpfile = Rc::new(RefCell::new(tmpfile));
-    let space_between_buttons = conf.space_between_buttons;
+    if clops.is_present("urgent") {
+        let (wins, _geom, _desktop, active) = get_wm_data();
+        let (xcb_conn, _screen_id) = xcb::Connection::connect(None).expect("XCB connection failed");
+        let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+        match most_recent_urgent(&wins, &conf.blacklist) {
+            Some(w) => {
+                tmpfile
+                    .borrow_mut()
+                    .write(&format!("{}", active.resource_id()).into_bytes()[..])
+                    .expect("failed writing to tmpfile");
+                go_to_window(w, &ewmh_conn);
+            }
+            None => {
+                println!("-- no window demands attention");
+                if let Some(x) = prev_win {
+                    tmpfile
+                        .borrow_mut()
+                        .write(&format!("{}", x).into_bytes()[..])
+                        .expect("failed writing to tmpfile");
+                }
+            }
+        }
+        return Ok(());
+    }
 
     let application = gtk::Application::builder()
         .application_id("com.andreimikhailov.winterreise")
@@ -83,9 +131,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     check_css(&css);
     let tilings = Path::join(&config_dir, "tilings.xml");
     check_tilings(&tilings);
+    let wrap_desktops = conf.wrap_desktops;
     let blacklist = Rc::new(conf.blacklist);
     application.connect_activate(move |app| {
         let (wins, _geom, desktop, active) = get_wm_data();
+        let desktop_names = get_desktop_names();
+        let number_of_desktops = desktop_names.len() as u32;
+        let layout = DesktopLayout::get(number_of_desktops);
         let provider = gtk::CssProvider::new();
         match css.to_str() {
             Some(x) => {
@@ -109,10 +161,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let (vbox, charhints) = make_vbox(
             &wins,
             if clops.is_present("current") { Some(desktop) } else { None },
-            space_between_buttons,
-            maxlen,
+            desktop,
+            &desktop_names,
             &blacklist,
-            &active
+            &active,
+            &row_style
             );
         window.add(&vbox);
         let hints = Rc::new(charhints);
@@ -145,21 +198,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 return Propagation::Stop;
             }
+            let new_desktop = match desktop_for_key(*keyval) {
+                Some(d) => Some(d),
+                None => direction_for_key(*keyval).and_then(|direction| {
+                    layout.relative_desktop(desktop, number_of_desktops, direction, wrap_desktops)
+                }),
+            };
+            if let Some(d) = new_desktop {
+                if d >= number_of_desktops {
+                    println!("-- there is no desktop {}", d + 1);
+                    return Propagation::Proceed;
+                }
+                app.quit();
+                let (xcb_conn, _screen_id) = xcb::Connection::connect(None).expect("XCB connection failed");
+                let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+                tmpfile.borrow_mut().write(&format!("{}",active.resource_id()).into_bytes()[..]).expect("failed writing to tmpfile");
+                let chdt_req = ewmh::proto::SendCurrentDesktop::new(&ewmh_conn, d);
+                ewmh_conn.send_and_check_request(&chdt_req).expect("Failed to change desktop");
+                return Propagation::Stop;
+            }
             let a = (format!("{}",*keyval)).parse::<u8>();
             match a {
                 Ok(aa) => {
                     app.quit();
                     let (xcb_conn, _screen_id) = xcb::Connection::connect(None).expect("XCB connection failed");
                     let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
-                    if aa < 97 && aa > 48 {
-                        tmpfile.borrow_mut().write(&format!("{}",active.resource_id()).into_bytes()[..]).expect("failed writing to tmpfile");
-                        let new_desktop = (aa - 49) as u32;
-                        let chdt_req = ewmh::proto::SendCurrentDesktop::new(&ewmh_conn, new_desktop);
-                        ewmh_conn.send_and_check_request(&chdt_req).expect("Failed to change desktop");
-                        return Propagation::Stop;
-                    } else  if let Some(s) = &hints.get(&(aa - 97)) {
+                    if let Some(s) = aa.checked_sub(97).and_then(|h| hints.get(&h)) {
                         tmpfile.borrow_mut().write(&format!("{}",active.resource_id()).into_bytes()[..]).expect("failed to write to tmpfile");
-                        go_to_window(**s, &ewmh_conn);
+                        go_to_window(*s, &ewmh_conn);
                         return Propagation::Stop;
                     } else {
                         return Propagation::Proceed;
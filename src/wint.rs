@@ -1,3 +1,4 @@
+extern crate clap;
 extern crate dirs;
 extern crate gdk;
 extern crate gdk_sys;
@@ -7,100 +8,752 @@ extern crate gtk;
 extern crate xcb_wm;
 
 use crate::gdk::prelude::{ApplicationExt, ApplicationExtManual};
+use clap::{App, Arg, ArgGroup, ArgMatches};
 use glib::clone;
 use glib::signal::Propagation;
 use gtk::prelude::*;
-use std::fs::File;
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use xcb::x::Window;
+use xcb::Xid;
+use xcb_wm::ewmh;
 
+use winterreise::capture::record_geometry;
+use winterreise::command::{
+    complete, parse_capture, parse_command, parse_generated, parse_move, parse_rearrangement,
+    Rearrangement,
+};
+use winterreise::generated::{Cells, Generated, DEFAULT_GRID, DEFAULT_MASTER_RATIO};
+use winterreise::history::{History, SavedWindow};
+use winterreise::keyboard::{Direction, KeyboardMove, Steps};
+use winterreise::session::{Session, SessionWindow};
+use winterreise::size_hints::Anchor;
+use winterreise::tilings::{get_geometry, Displays, FrameMode, Placement, Rect, Rule};
 use winterreise::{
-    check_css, check_tilings, get_conf, get_config_dir, get_wm_data, make_vbox, Config,
+    check_css, check_tilings, find_monitor, get_conf, get_config_dir, get_desktop_names,
+    get_frame_extents, get_monitors, get_outer_rect, get_size_hints, get_window_rect, get_wm_data,
+    get_workarea, has_static_gravity, hinted_windows, make_vbox, monitor_at, restore_window,
+    save_session_window, save_window, send_wm_message, session_path, find_tmpfile_path, unmaximize,
+    window_monitor, wm_supports, BlacklistedItems, Config, Monitor, RowStyle, TMPFile, WinInfo, WintError,
 };
 
-#[macro_use]
-extern crate serde_derive;
-extern crate serde;
-extern crate serde_xml_rs;
-
-#[derive(Debug, Deserialize)]
-struct WindowSimple {
-    #[serde(rename = "@nick", default)]
-    pub nick: String,
-    #[serde(rename = "@geometry", default)]
-    pub geometry: String,
+fn do_resize(
+    xconn: &xcb::Connection,
+    ewmh_conn: &ewmh::Connection,
+    wid: Window,
+    placement: &Placement,
+) -> Result<(), WintError> {
+    unmaximize(xconn, ewmh_conn, wid);
+    let extents = get_frame_extents(xconn, ewmh_conn, wid);
+    let client = placement.frame.client_rect(&placement.rect, extents);
+    // e.g. a terminal only takes whole characters, so it may end up smaller than its tiling
+    let client = get_size_hints(xconn, wid).fit_rect(&client, placement.anchor);
+    let (frame_x, frame_y) = (client.x - extents[0] as i32, client.y - extents[2] as i32);
+    let result = if wm_supports(ewmh_conn, ewmh_conn.atoms._NET_MOVERESIZE_WINDOW) {
+        // NorthWest gravity, with x, y, width and height present, and source indication 2 (a pager or similar tool)
+        let flags = xcb::x::Gravity::NorthWest as u32 | 0xf << 8 | 2 << 12;
+        let data = [
+            flags,
+            frame_x as u32,
+            frame_y as u32,
+            client.width,
+            client.height,
+        ];
+        send_wm_message(xconn, wid, ewmh_conn.atoms._NET_MOVERESIZE_WINDOW, data)
+    } else {
+        // with the (default) NorthWest gravity, the window manager puts the frame at the requested position
+        let (x, y) = if has_static_gravity(xconn, wid) {
+            (client.x, client.y)
+        } else {
+            (frame_x, frame_y)
+        };
+        let req = xcb::x::ConfigureWindow {
+            window: wid,
+            value_list: &[
+                xcb::x::ConfigWindow::X(x),
+                xcb::x::ConfigWindow::Y(y),
+                xcb::x::ConfigWindow::Width(client.width),
+                xcb::x::ConfigWindow::Height(client.height),
+            ],
+        };
+        let cookie = xconn.send_request_checked(&req);
+        xconn.check_request(cookie)
+    };
+    match result {
+        Ok(_) => println!(
+            "Resized window {:?} to {:?} (frame extents {:?})",
+            wid, placement.rect, extents
+        ),
+        Err(ref e) => println!("Error resizing window {:?}: {:?}", wid, e),
+    }
+    result.map_err(|e| xcb::Error::Protocol(e).into())
 }
 
-#[derive(Debug, Deserialize)]
-struct Display {
-    #[serde(rename = "@resolution", default)]
-    pub resolution: String,
+/// How long typing has to pause before the previews follow the command, in milliseconds
+const PREVIEW_DELAY_MS: u64 = 150;
+
+/// The windows to tile, with their placements
+type Tilings = Vec<(Window, Placement)>;
 
-    #[serde(rename = "window", default)]
-    pub windows: Vec<WindowSimple>,
+/// Splits `atex>2` into the command `atex` and the monitor `2`
+fn split_target(com: &str) -> (&str, Option<&str>) {
+    match com.split_once('>') {
+        Some((c, t)) => (c, Some(t)),
+        None => (com, None),
+    }
 }
 
-#[derive(Debug, Deserialize, Default)]
-#[serde(rename = "displays", default)]
-struct Displays {
-    #[serde(rename = "display", default)]
-    pub items: Vec<Display>,
+/// Works out where windows on the current desktop go, and puts them there
+struct Tiler<'a> {
+    xcb_conn: &'a xcb::Connection,
+    ewmh_conn: &'a ewmh::Connection<'a>,
+    xml_path: &'a PathBuf,
+    monitors: &'a [Monitor],
+    workarea: Rect,
+    /// where the file keeping the geometries from before the last tilings is, next to the `tmpfile`
+    tmpfile: &'a TMPFile,
 }
 
-fn get_geometry(xml_path: &PathBuf, nick: String, geom: &String) -> Option<Vec<u32>> {
-    let tilings: Displays = serde_xml_rs::from_reader(File::open(xml_path).unwrap()).unwrap();
-    let x = tilings
-        .items
-        .iter()
-        .filter(|disp| &disp.resolution == geom)
-        .next()
-        .unwrap();
-    x.windows
+impl Tiler<'_> {
+    /// The file keeping the geometries from before the last tilings; only looked up when needed,
+    /// so that tilings without undo work without `XDG_RUNTIME_DIR`
+    fn history_path(&self) -> Result<PathBuf, WintError> {
+        find_tmpfile_path(self.tmpfile).map(|p| PathBuf::from(format!("{}-history", p)))
+    }
+
+    /// The placement of `wid` as `nick`, on the monitor `target` if given (otherwise on the monitor
+    /// where the window is). Without a nick, the window keeps its geometry but moves to `target`;
+    /// a nick like `@0:0-1:1` is a block of cells of a grid
+    fn place(&self, wid: Window, nick: &str, target: Option<&str>) -> Result<Placement, WintError> {
+        let current = window_monitor(self.xcb_conn, self.ewmh_conn, self.monitors, wid);
+        let monitor = match target {
+            Some(t) => find_monitor(self.monitors, t)?,
+            None => current,
+        };
+        let area = monitor.rect.clip(&self.workarea);
+        if let Some(spec) = nick.strip_prefix('@') {
+            let tilings = Displays::read(self.xml_path).unwrap_or_default();
+            let cells = Cells::parse(spec, tilings.grid.as_deref().unwrap_or(DEFAULT_GRID))?;
+            let spacing = tilings.spacing(&monitor.name, &monitor.rect.resolution());
+            let inner = spacing.inner(&area);
+            let rect = cells.rect(&inner, spacing.gap);
+            return Ok(Placement {
+                rect,
+                frame: FrameMode::Outer,
+                rule: Rule::Cell(nick.to_string()),
+                anchor: tilings
+                    .anchor("", &rect, &inner)
+                    .map_err(WintError::BadGeometry)?,
+            });
+        }
+        if nick.is_empty() && target.is_some() {
+            let rect = get_outer_rect(self.xcb_conn, self.ewmh_conn, wid).ok_or(
+                WintError::UnknownWindow(format!("{:#x}", wid.resource_id())),
+            )?;
+            return Ok(Placement {
+                rect: rect.moved(&current.rect.clip(&self.workarea), &area),
+                frame: FrameMode::Outer,
+                rule: Rule::Moved,
+                anchor: Anchor::default(),
+            });
+        }
+        get_geometry(
+            self.xml_path,
+            nick.to_string(),
+            &monitor.name,
+            &monitor.rect,
+            &area,
+        )?
+        .ok_or(WintError::UnknownNick(nick.to_string()))
+    }
+
+    /// The placements for a command like `atex cpdf`, where the charhints refer to the `hinted` windows,
+    /// for the name of a layout, for a generated layout like `:tall`, or for a rearrangement like `a<>c`
+    fn command(&self, command: &str, hinted: &[&WinInfo]) -> Result<Tilings, WintError> {
+        if parse_move(command, hinted.len()).is_some() {
+            return Err(WintError::BadCommand(String::from(
+                ":move needs the arrow keys, so it only works in the dialog",
+            )));
+        }
+        if let Some(rearrangement) = parse_rearrangement(command, hinted.len()) {
+            return self.rearrange(&rearrangement?, hinted);
+        }
+        if let Some(generated) = parse_generated(command, hinted.len()) {
+            let generated = generated?;
+            let wids: Vec<Window> = generated.indices.iter().map(|i| hinted[*i].0).collect();
+            return self.generated(generated.name, generated.layout, &wids);
+        }
+        if let Some(tilings) = self.layout(command.trim(), hinted)? {
+            return Ok(tilings);
+        }
+        parse_command(command, hinted.len())?
+            .iter()
+            .map(|step| {
+                let wid = hinted[step.index].0;
+                Ok((wid, self.place(wid, &step.nick, step.target.as_deref())?))
+            })
+            .collect()
+    }
+
+    /// The placements for the `<layout>` called `name`, with its roles given to the `hinted` windows;
+    /// `None` if there is no such layout
+    fn layout(&self, name: &str, hinted: &[&WinInfo]) -> Result<Option<Tilings>, WintError> {
+        let tilings = Displays::read(self.xml_path)?;
+        let layout = match tilings.layout(name) {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+        let windows: Vec<(Window, &str, &str)> = hinted
+            .iter()
+            .map(|win| (win.0, win.3.as_str(), win.2.as_str()))
+            .collect();
+        let mut placements = vec![];
+        for (wid, nick) in layout.assign(&windows) {
+            placements.push((wid, self.place(wid, nick, None)?));
+        }
+        Ok(Some(placements))
+    }
+
+    /// The placements of the windows `wids` by a generated layout, on the monitor of the first of them
+    fn generated(
+        &self,
+        name: &str,
+        generated: Generated,
+        wids: &[Window],
+    ) -> Result<Tilings, WintError> {
+        let first = match wids.first() {
+            Some(w) => *w,
+            None => return Ok(vec![]),
+        };
+        let monitor = window_monitor(self.xcb_conn, self.ewmh_conn, self.monitors, first);
+        let area = monitor.rect.clip(&self.workarea);
+        // the generated layouts do not need tilings.xml, only its settings if it has them
+        let tilings = Displays::read(self.xml_path).unwrap_or_default();
+        let ratio = tilings.master_ratio.unwrap_or(DEFAULT_MASTER_RATIO);
+        let spacing = tilings.spacing(&monitor.name, &monitor.rect.resolution());
+        let inner = spacing.inner(&area);
+        let rects = generated.rects(wids.len(), &inner, spacing.gap, ratio);
+        wids.iter()
+            .zip(rects)
+            .map(|(wid, rect)| {
+                let placement = Placement {
+                    rect,
+                    frame: FrameMode::Outer,
+                    rule: Rule::Generated(name.to_string()),
+                    anchor: tilings
+                        .anchor("", &rect, &inner)
+                        .map_err(WintError::BadGeometry)?,
+                };
+                Ok((*wid, placement))
+            })
+            .collect()
+    }
+
+    /// The placements of the `hinted` windows swapped, rotated or mirrored, from their current geometries
+    fn rearrange(
+        &self,
+        rearrangement: &Rearrangement,
+        hinted: &[&WinInfo],
+    ) -> Result<Tilings, WintError> {
+        let (indices, rule) = match rearrangement {
+            Rearrangement::Rotate(indices) => (indices, Rule::Swapped),
+            Rearrangement::Mirror(indices) => (indices, Rule::Mirrored),
+        };
+        let mut windows = vec![];
+        for i in indices {
+            let wid = hinted[*i].0;
+            let rect = get_outer_rect(self.xcb_conn, self.ewmh_conn, wid).ok_or(
+                WintError::UnknownWindow(format!("{:#x}", wid.resource_id())),
+            )?;
+            windows.push((wid, rect));
+        }
+        let mut tilings = vec![];
+        for (i, (wid, rect)) in windows.iter().enumerate() {
+            let new_rect = match rearrangement {
+                Rearrangement::Rotate(_) => windows[(i + 1) % windows.len()].1,
+                Rearrangement::Mirror(_) => {
+                    let area = window_monitor(self.xcb_conn, self.ewmh_conn, self.monitors, *wid)
+                        .rect
+                        .clip(&self.workarea);
+                    Rect {
+                        x: area.x + area.right() - rect.right(),
+                        ..*rect
+                    }
+                }
+            };
+            let area = monitor_at(self.monitors, &new_rect)
+                .map(|m| m.rect.clip(&self.workarea))
+                .unwrap_or(self.workarea);
+            let placement = Placement {
+                rect: new_rect,
+                frame: FrameMode::Outer,
+                rule: rule.clone(),
+                anchor: Anchor::auto(&new_rect, &area),
+            };
+            tilings.push((*wid, placement));
+        }
+        Ok(tilings)
+    }
+
+    /// Starts moving `wid` with the arrow keys, from its current geometry; it snaps to the edges of the work area
+    /// of its monitor and of the other `hinted` windows
+    fn keyboard_move(
+        &self,
+        wid: Window,
+        hinted: &[&WinInfo],
+        steps: Steps,
+    ) -> Result<KeyboardMove, WintError> {
+        let rect = get_outer_rect(self.xcb_conn, self.ewmh_conn, wid).ok_or(
+            WintError::UnknownWindow(format!("{:#x}", wid.resource_id())),
+        )?;
+        let area = window_monitor(self.xcb_conn, self.ewmh_conn, self.monitors, wid)
+            .rect
+            .clip(&self.workarea);
+        let others: Vec<Rect> = hinted
+            .iter()
+            .filter(|win| win.0 != wid)
+            .filter_map(|win| get_outer_rect(self.xcb_conn, self.ewmh_conn, win.0))
+            .collect();
+        Ok(KeyboardMove::new(rect, &area, &others, steps))
+    }
+
+    /// Records in the history what the windows are like now, before they are moved
+    fn remember(&self, wids: &[Window]) {
+        let saved: Vec<SavedWindow> = wids
+            .iter()
+            .filter_map(|wid| save_window(self.xcb_conn, self.ewmh_conn, *wid))
+            .collect();
+        let path = match self.history_path() {
+            Ok(path) => path,
+            Err(e) => {
+                println!("ERROR: could not record the tiling for undo: {}", e);
+                return;
+            }
+        };
+        let mut history = History::read(&path);
+        history.push(saved);
+        if let Err(e) = history.write(&path) {
+            println!("ERROR: could not write {:?}: {}", path, e);
+        }
+    }
+
+    /// Moves and resizes the windows; all of them, even if some fail. What the windows were like before
+    /// goes into the history
+    fn apply(&self, tilings: &[(Window, Placement)]) -> Result<(), WintError> {
+        let wids: Vec<Window> = tilings.iter().map(|(wid, _)| *wid).collect();
+        self.remember(&wids);
+        let mut result = Ok(());
+        for (wid, placement) in tilings.iter() {
+            println!("Geometry for window {:?} from {}", wid, placement.rule);
+            if let Err(e) = do_resize(self.xcb_conn, self.ewmh_conn, *wid, placement) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Writes the current geometry of `wid` into `tilings.xml` as `nick`, in the section for the resolution
+    /// of its monitor (or for its output, if the nick is there), in pixels from the top left corner of the monitor
+    fn capture(&self, wid: Window, nick: &str) -> Result<(), WintError> {
+        let unknown = || WintError::UnknownWindow(format!("{:#x}", wid.resource_id()));
+        let outer = get_outer_rect(self.xcb_conn, self.ewmh_conn, wid).ok_or_else(unknown)?;
+        let client = get_window_rect(self.xcb_conn, wid).ok_or_else(unknown)?;
+        let monitor = window_monitor(self.xcb_conn, self.ewmh_conn, self.monitors, wid);
+        let relative = |r: Rect| {
+            format!(
+                "{},{},{},{}",
+                r.x - monitor.rect.x,
+                r.y - monitor.rect.y,
+                r.width,
+                r.height
+            )
+        };
+        let resolution = monitor.rect.resolution();
+        let rule =
+            record_geometry(
+                self.xml_path,
+                &monitor.name,
+                &resolution,
+                nick,
+                &|frame| match frame {
+                    FrameMode::Outer => relative(outer),
+                    FrameMode::Client => relative(client),
+                },
+            )?;
+        println!(
+            "Captured window {:?} as {} for {} ({}): {:?}",
+            wid, nick, resolution, rule, outer
+        );
+        Ok(())
+    }
+
+    fn clients(&self) -> Result<Vec<Window>, WintError> {
+        let clients_cookie = self.ewmh_conn.send_request(&ewmh::proto::GetClientList);
+        Ok(self.ewmh_conn.wait_for_reply(clients_cookie)?.clients)
+    }
+
+    /// Puts the windows of the last tiling back where they were, and removes it from the history
+    fn undo(&self) -> Result<(), WintError> {
+        let path = self.history_path()?;
+        let mut history = History::read(&path);
+        let saved = history.pop().ok_or(WintError::NothingToUndo)?;
+        history.write(&path)?;
+        let clients = self.clients()?;
+        let mut restored = vec![];
+        for s in saved.iter() {
+            match clients.iter().find(|w| w.resource_id() == s.id) {
+                Some(w) => restored.push((*w, s)),
+                None => println!("Window {:#x} is gone", s.id),
+            }
+        }
+        self.put_back(&restored, Rule::Undone)
+    }
+
+    /// The client windows which go into sessions, i.e. not panels, the desktop or blacklisted windows, as they are now
+    fn session_windows(
+        &self,
+        blacklist: &BlacklistedItems,
+    ) -> Result<Vec<(Window, SessionWindow)>, WintError> {
+        Ok(self
+            .clients()?
+            .iter()
+            .filter_map(|wid| {
+                save_session_window(self.xcb_conn, self.ewmh_conn, *wid, blacklist)
+                    .map(|w| (*wid, w))
+            })
+            .collect())
+    }
+
+    /// Writes the geometries, desktops and states of the client windows into the session file `path`
+    fn save_session(&self, path: &Path, blacklist: &BlacklistedItems) -> Result<(), WintError> {
+        let session = Session {
+            windows: self
+                .session_windows(blacklist)?
+                .into_iter()
+                .map(|(_, w)| w)
+                .collect(),
+        };
+        session.write(path)?;
+        println!("Saved {} windows into {:?}", session.windows.len(), path);
+        Ok(())
+    }
+
+    /// Puts the open windows back as they were in the session `name`, matching them by class and title;
+    /// panels, the desktop and blacklisted windows stay where they are. This goes into the history like any tiling
+    fn restore_session(
+        &self,
+        name: &str,
+        session: &Session,
+        blacklist: &BlacklistedItems,
+    ) -> Result<(), WintError> {
+        let current = self.session_windows(blacklist)?;
+        let windows: Vec<(Window, u32, &str, &str, &str)> = current
+            .iter()
+            .map(|(wid, w)| {
+                let (class, res_name, title) = (&w.class, &w.res_name, &w.title);
+                (
+                    *wid,
+                    w.saved.id,
+                    class.as_str(),
+                    res_name.as_str(),
+                    title.as_str(),
+                )
+            })
+            .collect();
+        let restored: Vec<(Window, &SavedWindow)> = session
+            .assign(&windows)
+            .into_iter()
+            .map(|(wid, w)| (wid, &w.saved))
+            .collect();
+        println!(
+            "{} of the {} windows of the session {:?} are open",
+            restored.len(),
+            session.windows.len(),
+            name
+        );
+        let wids: Vec<Window> = restored.iter().map(|(wid, _)| *wid).collect();
+        self.remember(&wids);
+        self.put_back(&restored, Rule::Session(name.to_string()))
+    }
+
+    /// Gives the windows their saved geometries, desktops and states; all of them, even if some fail
+    fn put_back(&self, restored: &[(Window, &SavedWindow)], rule: Rule) -> Result<(), WintError> {
+        let mut result = Ok(());
+        for &(wid, s) in restored.iter() {
+            let placement = Placement {
+                rect: s.rect,
+                frame: FrameMode::Outer,
+                rule: rule.clone(),
+                anchor: Anchor::default(),
+            };
+            println!("Geometry for window {:?} from {}", wid, placement.rule);
+            if let Err(e) = do_resize(self.xcb_conn, self.ewmh_conn, wid, &placement) {
+                result = Err(e);
+            }
+            if let Err(e) = restore_window(self.xcb_conn, self.ewmh_conn, wid, s) {
+                println!("Error restoring the state of window {:?}: {:?}", wid, e);
+                result = Err(xcb::Error::Protocol(e).into());
+            }
+        }
+        result
+    }
+}
+
+/// Finds a managed window by its id, in decimal or in hex (`0x...`)
+fn find_window(wins: &[WinInfo], id: &str) -> Result<Window, WintError> {
+    let parsed = match id.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse::<u32>(),
+    };
+    parsed
+        .ok()
+        .and_then(|id| wins.iter().find(|win| win.0.resource_id() == id))
+        .map(|win| win.0)
+        .ok_or(WintError::UnknownWindow(id.to_string()))
+}
+
+/// The placements asked for on the command line, instead of in the dialog
+fn cli_tilings(
+    clops: &ArgMatches,
+    tiler: &Tiler,
+    hinted: &[&WinInfo],
+    wins: &[WinInfo],
+    active: Window,
+) -> Result<Tilings, WintError> {
+    if let Some(name) = clops.value_of("layout") {
+        if name.starts_with(':') {
+            return tiler.command(name, hinted);
+        }
+        return tiler
+            .layout(name, hinted)?
+            .ok_or(WintError::UnknownLayout(name.to_string()));
+    }
+    if let Some(command) = clops.value_of("apply") {
+        return tiler.command(command, hinted);
+    }
+    let (wid, nick) = match clops.value_of("active") {
+        Some(nick) => (active, nick),
+        None => {
+            let args: Vec<&str> = clops.values_of("window").unwrap().collect();
+            let nick = args.get(1).ok_or(WintError::BadCommand(String::from(
+                "--window needs a NICK after the ID, unless used with --capture",
+            )))?;
+            (find_window(wins, args[0])?, *nick)
+        }
+    };
+    let (nick, target) = split_target(nick);
+    Ok(vec![(wid, tiler.place(wid, nick, target)?)])
+}
+
+/// Shows translucent rectangles where the windows would go, labelled with their charhints
+fn show_previews(tilings: &[(Window, Placement)], hinted: &[WinInfo]) -> Vec<gtk::Window> {
+    let visual = gdk::Screen::default().and_then(|scr| scr.rgba_visual());
+    tilings
         .iter()
-        .filter(|w| w.nick == nick)
-        .next()
-        .map(|ni| {
-            ni.geometry
-                .split(",")
-                .map(|s| str::parse::<u32>(s).unwrap())
-                .collect()
+        .map(|(wid, placement)| {
+            // a popup window is override-redirect, so the window manager leaves it alone
+            let preview = gtk::Window::new(gtk::WindowType::Popup);
+            preview.set_visual(visual.as_ref());
+            preview.style_context().add_class("wint_preview");
+            let hint = match hinted.iter().position(|win| win.0 == *wid) {
+                Some(i) => ((b'a' + i as u8) as char).to_string(),
+                None => String::new(),
+            };
+            let label = gtk::Label::new(Some(&hint));
+            label.style_context().add_class("wint_preview_label");
+            preview.add(&label);
+            let rect = placement.rect;
+            preview.move_(rect.x, rect.y);
+            preview.set_default_size(rect.width as i32, rect.height as i32);
+            preview.show_all();
+            preview
         })
+        .collect()
 }
 
-fn do_resize(xconn: &xcb::Connection, wid: Window, g: &Vec<u32>) {
-    let req = xcb::x::ConfigureWindow {
-        window: wid,
-        value_list: &[
-            xcb::x::ConfigWindow::X(g[0] as i32),
-            xcb::x::ConfigWindow::Y(g[1] as i32),
-            xcb::x::ConfigWindow::Width(g[2] as u32),
-            xcb::x::ConfigWindow::Height(g[3] as u32),
-        ],
+/// Where a window moved with the arrow keys goes, as its outer frame
+fn keyboard_placement(keyboard: &KeyboardMove) -> Placement {
+    Placement {
+        rect: keyboard.rect,
+        frame: FrameMode::Outer,
+        rule: Rule::Keyboard,
+        anchor: Anchor::default(),
+    }
+}
+
+/// The nicks which can be used on any of the monitors
+fn all_nicks(xml_path: &Path, monitors: &[Monitor]) -> Vec<String> {
+    let tilings = match Displays::read(xml_path) {
+        Ok(t) => t,
+        Err(_) => return vec![],
     };
-    let cookie = xconn.send_request_checked(&req);
-    match xconn.check_request(cookie) {
-        Ok(_) => println!("Resized window {:?} to {:?}", wid, g),
-        Err(e) => println!("Error resizing window {:?}: {:?}", wid, e),
+    let mut nicks: Vec<String> = vec![];
+    for m in monitors {
+        for nick in tilings.nicks(&m.name, &m.rect.resolution()) {
+            if !nicks.contains(&nick) {
+                nicks.push(nick);
+            }
+        }
     }
+    nicks
+}
+
+/// Tells which section of `tilings.xml` serves each monitor
+fn status_message(xml_path: &Path, monitors: &[Monitor]) -> String {
+    let tilings = match Displays::read(xml_path) {
+        Ok(t) => t,
+        Err(e) => return format!("could not read tilings.xml: {}", e),
+    };
+    let messages: Vec<String> = monitors
+        .iter()
+        .map(|m| {
+            let geom = m.rect.resolution();
+            let msg = match tilings.rule_for(&m.name, &geom) {
+                Some(Rule::Exact) => format!("tilings for {}", geom),
+                Some(Rule::Output(name)) => format!("tilings for output {}", name),
+                Some(rule) => format!("no tilings for {}, using {}", geom, rule),
+                None => format!("no tilings for {}", geom),
+            };
+            if monitors.len() > 1 {
+                format!("{}: {}", m.name, msg)
+            } else {
+                msg
+            }
+        })
+        .collect();
+    messages.join("; ")
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clops = App::new("wint")
+        .author("Andrei Mikhailov")
+        .about("Window tiling")
+        .arg(
+            Arg::with_name("layout")
+                .help(
+                    "apply the layout NAME from tilings.xml (or a generated one, like :tall) to the windows on the current desktop",
+                )
+                .short("l")
+                .long("layout")
+                .value_name("NAME")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("apply")
+                .help("apply a command like \"atex cpdf\", as if typed in the dialog")
+                .short("a")
+                .long("apply")
+                .value_name("COMMAND")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("active")
+                .help("tile the active window as NICK")
+                .long("active")
+                .value_name("NICK")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("window")
+                .help("tile the window with the id ID as NICK (--window ID NICK), or pick the window for --capture")
+                .short("w")
+                .long("window")
+                .value_name("ID")
+                .min_values(1)
+                .max_values(2)
+                .conflicts_with_all(&["layout", "apply", "active", "undo", "save-session", "restore-session"]),
+        )
+        .arg(
+            Arg::with_name("save-session")
+                .help("record the geometries, desktops and states of all windows as the session NAME")
+                .long("save-session")
+                .value_name("NAME")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("restore-session")
+                .help("put the open windows back as they were in the session NAME")
+                .long("restore-session")
+                .value_name("NAME")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("capture")
+                .help("write the geometry of the active window (or of the one given by --window) into tilings.xml as NICK")
+                .short("c")
+                .long("capture")
+                .value_name("NICK")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("undo")
+                .help("put the windows of the last tiling back where they were")
+                .short("u")
+                .long("undo"),
+        )
+        .group(ArgGroup::with_name("batch").args(&["layout", "apply", "active", "undo", "capture", "save-session", "restore-session"]))
+        .get_matches();
     let config_dir = get_config_dir();
     let conf: Config = get_conf().expect("Could not read the configuration file");
-    let maxlen = conf.maxwidth;
+    let row_style = RowStyle::from_config(&conf);
+    let keyboard_steps = Steps {
+        move_step: conf.move_step,
+        resize_step: conf.resize_step,
+        snap: conf.snap_distance,
+    };
     let blacklist = Rc::new(conf.blacklist);
-    let space_between_buttons = conf.space_between_buttons;
-    let (wins, geom, desktop, active) = get_wm_data();
+    let (wins, _geom, desktop, active) = get_wm_data();
+    let monitors = Rc::new(get_monitors());
+    let desktop_names = get_desktop_names();
 
     let application = gtk::Application::builder()
         .application_id("com.andreimikhailov.winterreise")
         .build();
     let xml_path = Path::join(&config_dir, "tilings.xml");
     check_tilings(&xml_path);
+    if clops.is_present("batch") || clops.is_present("window") {
+        let (xcb_conn, _screen_id) = xcb::Connection::connect(None)?;
+        let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+        let tiler = Tiler {
+            xcb_conn: &xcb_conn,
+            ewmh_conn: &ewmh_conn,
+            xml_path: &xml_path,
+            monitors: &monitors,
+            workarea: get_workarea(desktop),
+            tmpfile: &conf.tmpfile,
+        };
+        let hinted = hinted_windows(&wins, desktop, &blacklist, &row_style);
+        let result = if clops.is_present("undo") {
+            tiler.undo()
+        } else if let Some(name) = clops.value_of("save-session") {
+            session_path(name).and_then(|path| tiler.save_session(&path, &blacklist))
+        } else if let Some(name) = clops.value_of("restore-session") {
+            session_path(name)
+                .and_then(|path| {
+                    Session::read(&path).map_err(|_| WintError::UnknownSession(name.to_string()))
+                })
+                .and_then(|session| tiler.restore_session(name, &session, &blacklist))
+        } else if let Some(nick) = clops.value_of("capture") {
+            match clops.values_of("window") {
+                Some(mut args) => find_window(&wins, args.next().unwrap()),
+                None => Ok(active),
+            }
+            .and_then(|wid| tiler.capture(wid, nick))
+        } else {
+            cli_tilings(&clops, &tiler, &hinted, &wins, active)
+                .and_then(|tilings| tiler.apply(&tilings))
+        };
+        if let Err(e) = result {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let css = Path::join(&config_dir, "style.css");
     check_css(&css);
     let xml_path = Rc::new(xml_path);
+    let tmpfile = Rc::new(conf.tmpfile);
     application.connect_activate(move |app| {
         let provider = gtk::CssProvider::new();
         match css.to_str() {
@@ -130,36 +783,189 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else { return Propagation::Proceed; }
         }));
 
-        let (vbox, charhints) = make_vbox(&wins, Some(desktop), space_between_buttons, maxlen, &blacklist, &active);
+        let (vbox, _charhints) = make_vbox(&wins, Some(desktop), desktop, &desktop_names, &blacklist, &active, &row_style);
+        let hinted: Rc<Vec<WinInfo>> = Rc::new(hinted_windows(&wins, desktop, &blacklist, &row_style).into_iter().cloned().collect());
         window.add(&vbox);
         let entry = gtk::Entry::new();
         entry.style_context().add_class("wmjump_cmd_entry");
-        let geom1 = Rc::clone(&geom);
-        println!("Geometry={:?}", geom1);
+        let monitors = Rc::clone(&monitors);
+        println!("Monitors={:?}", monitors);
+        let workarea = get_workarea(desktop);
+        println!("Work area={:?}", workarea);
         let xml_path = Rc::clone(&xml_path);
-        entry.connect_activate(clone!(@weak entry, @weak app => move |_| {
+        let status = gtk::Label::new(Some(&status_message(&xml_path, &monitors)));
+        status.style_context().add_class("wint_status");
+        let nicks = Rc::new(all_nicks(&xml_path, &monitors));
+        let nick_list = gtk::Label::new(Some(&format!("nicks: {}", nicks.join(" "))));
+        nick_list.set_line_wrap(true);
+        nick_list.style_context().add_class("wint_nicks");
+        // Tab completes the nick being typed; pressing it again (or Shift+Tab) cycles through the candidates.
+        // The state is the text shown after the last completion, the candidates, and which of them is shown
+        let completion: Rc<RefCell<Option<(String, Vec<String>, usize)>>> = Rc::new(RefCell::new(None));
+        entry.connect_key_press_event(move |entry, e| {
+            let keyval = *e.keyval();
+            let backwards = keyval == gdk_sys::GDK_KEY_ISO_Left_Tab as u32;
+            if keyval != gdk_sys::GDK_KEY_Tab as u32 && !backwards {
+                return Propagation::Proceed;
+            }
+            let text = entry.text().to_string();
+            let (candidates, i) = match completion.borrow_mut().take() {
+                Some((shown, candidates, i)) if shown == text => {
+                    let n = candidates.len();
+                    let i = if backwards { (i + n - 1) % n } else { (i + 1) % n };
+                    (candidates, i)
+                }
+                _ => (complete(&text, &nicks), 0),
+            };
+            if candidates.is_empty() {
+                return Propagation::Stop;
+            }
+            entry.set_text(&candidates[i]);
+            entry.set_position(-1);
+            *completion.borrow_mut() = Some((candidates[i].clone(), candidates, i));
+            Propagation::Stop
+        });
+        // While the command is being typed, the windows it would tile are previewed, once typing pauses
+        let previews: Rc<RefCell<Vec<gtk::Window>>> = Rc::new(RefCell::new(vec![]));
+        let pending_preview: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+        // After `:move a`, the window a being moved with the arrow keys; typing leaves this mode
+        let moving: Rc<RefCell<Option<(Window, KeyboardMove)>>> = Rc::new(RefCell::new(None));
+        entry.connect_changed(clone!(@strong previews, @strong pending_preview, @strong moving, @strong hinted, @strong xml_path, @strong monitors, @strong tmpfile => move |entry| {
+            for preview in previews.borrow_mut().drain(..) {
+                preview.close();
+            }
+            moving.borrow_mut().take();
+            if let Some(source) = pending_preview.borrow_mut().take() {
+                source.remove();
+            }
+            let command = entry.text().to_string();
+            let update = clone!(@strong previews, @strong pending_preview, @strong hinted, @strong xml_path, @strong monitors, @strong tmpfile => move || {
+                pending_preview.borrow_mut().take();
+                let (xcb_conn, _screen_id) = xcb::Connection::connect(None).expect("XCB connection failed");
+                let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+                let tiler = Tiler { xcb_conn: &xcb_conn, ewmh_conn: &ewmh_conn, xml_path: &xml_path, monitors: &monitors, workarea, tmpfile: &tmpfile };
+                let hinted_refs: Vec<&WinInfo> = hinted.iter().collect();
+                if let Ok(tilings) = tiler.command(&command, &hinted_refs) {
+                    *previews.borrow_mut() = show_previews(&tilings, &hinted);
+                }
+            });
+            let source = glib::timeout_add_local_once(std::time::Duration::from_millis(PREVIEW_DELAY_MS), update);
+            *pending_preview.borrow_mut() = Some(source);
+        }));
+        // The arrow keys move the window, and Shift+arrows resize it
+        entry.connect_key_press_event(clone!(@strong previews, @strong moving, @strong hinted => move |_, e| {
+            let keyval = *e.keyval();
+            let direction = if keyval == gdk_sys::GDK_KEY_Left as u32 {
+                Direction::Left
+            } else if keyval == gdk_sys::GDK_KEY_Right as u32 {
+                Direction::Right
+            } else if keyval == gdk_sys::GDK_KEY_Up as u32 {
+                Direction::Up
+            } else if keyval == gdk_sys::GDK_KEY_Down as u32 {
+                Direction::Down
+            } else {
+                return Propagation::Proceed;
+            };
+            let mut moving = moving.borrow_mut();
+            let (wid, keyboard) = match moving.as_mut() {
+                Some(m) => m,
+                None => return Propagation::Proceed,
+            };
+            if e.state().contains(gdk::ModifierType::SHIFT_MASK) {
+                keyboard.resize_by(direction);
+            } else {
+                keyboard.move_by(direction);
+            }
+            for preview in previews.borrow_mut().drain(..) {
+                preview.close();
+            }
+            *previews.borrow_mut() = show_previews(&[(*wid, keyboard_placement(keyboard))], &hinted);
+            Propagation::Stop
+        }));
+        entry.connect_activate(clone!(@weak entry, @weak status, @weak app => move |_| {
             let command : String = entry.text().to_string();
-            let tilings : Vec<(xcb::x::Window, Option<Vec<u32>>)> = command.split(" ").map(|com| {
-                let mut it = com.chars();
-                let charhint = it.next().unwrap();
-                let wid = *charhints.get(&(charhint as u8 - 97 as u8)).unwrap();
-                let tiling = it.collect::<String>();
-                let mg = get_geometry(&xml_path, tiling, &geom1);
-                return (wid, mg)
-            }).collect();
-            app.quit();
             let (xcb_conn, _screen_id) = xcb::Connection::connect(None).expect("XCB connection failed");
-            for (wid, mg) in tilings.iter() {
-                match mg {
-                    Some(g) => do_resize(&xcb_conn, *wid, &g),
-                    None => ()
+            let ewmh_conn = ewmh::Connection::connect(&xcb_conn);
+            let tiler = Tiler { xcb_conn: &xcb_conn, ewmh_conn: &ewmh_conn, xml_path: &xml_path, monitors: &monitors, workarea, tmpfile: &tmpfile };
+            let show_error = |e: WintError| {
+                println!("ERROR: {}", e);
+                status.set_text(&format!("{}", e));
+                status.style_context().add_class("wint_error");
+            };
+            // Enter puts the window moved with the arrow keys where it is shown
+            if let Some((wid, keyboard)) = moving.borrow_mut().take() {
+                for preview in previews.borrow_mut().drain(..) {
+                    preview.close();
+                }
+                app.quit();
+                let _ = tiler.apply(&[(wid, keyboard_placement(&keyboard))]);
+                return;
+            }
+            // `:move a` starts moving the window a with the arrow keys
+            if let Some(index) = parse_move(&command, hinted.len()) {
+                let hinted_refs: Vec<&WinInfo> = hinted.iter().collect();
+                let started = index.and_then(|i| {
+                    let wid = hinted[i].0;
+                    tiler.keyboard_move(wid, &hinted_refs, keyboard_steps).map(|k| (wid, k))
+                });
+                match started {
+                    Ok((wid, keyboard)) => {
+                        *previews.borrow_mut() = show_previews(&[(wid, keyboard_placement(&keyboard))], &hinted);
+                        *moving.borrow_mut() = Some((wid, keyboard));
+                        status.set_text("arrows move the window, Shift+arrows resize it, Enter puts it there, Escape cancels");
+                        status.style_context().remove_class("wint_error");
+                    }
+                    Err(e) => show_error(e),
+                }
+                return;
+            }
+            // `u` undoes the last tiling
+            if command.trim() == "u" {
+                let undoable = tiler.history_path().and_then(|path| {
+                    if History::read(&path).operations.is_empty() {
+                        Err(WintError::NothingToUndo)
+                    } else {
+                        Ok(())
+                    }
+                });
+                if let Err(e) = undoable {
+                    show_error(e);
+                    return;
                 }
+                app.quit();
+                let _ = tiler.undo();
+                return;
+            }
+            // `a=tex` writes the geometry of the window a into tilings.xml as tex
+            if let Some(step) = parse_capture(&command, hinted.len()) {
+                match step.and_then(|step| tiler.capture(hinted[step.index].0, &step.nick)) {
+                    Ok(()) => app.quit(),
+                    Err(e) => show_error(e),
+                }
+                return;
             }
+            let hinted_refs: Vec<&WinInfo> = hinted.iter().collect();
+            let tilings = tiler.command(&command, &hinted_refs);
+            let tilings = match tilings {
+                Ok(t) => t,
+                Err(e) => {
+                    show_error(e);
+                    return;
+                }
+            };
+            for preview in previews.borrow_mut().drain(..) {
+                preview.close();
+            }
+            app.quit();
+            let _ = tiler.apply(&tilings);
         }));
         vbox.add(&entry);
+        vbox.add(&nick_list);
+        vbox.add(&status);
         entry.grab_focus();
         window.show_all();
     });
-    application.run();
+    let empty: Vec<String> = vec![];
+    application.run_with_args(&empty);
     Ok(())
 }